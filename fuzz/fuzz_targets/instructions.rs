@@ -0,0 +1,15 @@
+//! Generated programs of valid instructions, so runs get past the first unknown opcode
+#![no_main]
+
+use chip8_rs_fuzz::{assemble, run, Op, Setup};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|input: (Setup, Vec<Op>)| {
+    let (setup, ops) = input;
+    let rom = assemble(&ops, setup.platform());
+    if let Some(mut machine) = setup.machine(&rom) {
+        // the generated code starts at 0x200, whatever pc the setup picked
+        machine.cpu.pc = 0x200;
+        run(&mut machine, setup.keys());
+    }
+});
@@ -0,0 +1,12 @@
+//! A ROM run from any machine state: registers, quirks, stack, resolution and keys
+#![no_main]
+
+use chip8_rs_fuzz::{run, Setup};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|input: (Setup, &[u8])| {
+    let (setup, rom) = input;
+    if let Some(mut machine) = setup.machine(rom) {
+        run(&mut machine, setup.keys());
+    }
+});
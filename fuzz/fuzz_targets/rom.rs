@@ -0,0 +1,22 @@
+//! Arbitrary bytes as a ROM, disassembled and run on every platform
+#![no_main]
+
+use chip8_rs::{disasm, rom, Machine};
+use chip8_rs::machine::UnknownOpcodePolicy;
+use chip8_rs_fuzz::{run, PLATFORMS};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    for platform in PLATFORMS {
+        let _ = disasm::disassemble(data, platform).to_string();
+
+        let mut machine = Machine::with_platform(platform);
+        // unknown opcodes are everywhere in random bytes, skipping them gets further
+        machine.unknown_opcodes = UnknownOpcodePolicy::Ignore;
+        if machine.load_rom(data).is_ok() {
+            // some keys held, changing every frame
+            let keys: Vec<u16> = data.chunks(2).take(16).map(|pair| rom::hash(pair) as u16).collect();
+            run(&mut machine, &keys);
+        }
+    }
+});
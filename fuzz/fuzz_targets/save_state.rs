@@ -0,0 +1,26 @@
+//! Arbitrary save states, after a header that matches the loaded ROM
+#![no_main]
+
+use chip8_rs::Machine;
+use chip8_rs::components::audio::NullSink;
+use chip8_rs_fuzz::run;
+use libfuzzer_sys::fuzz_target;
+
+/// Magic, format version and ROM hash
+const HEADER: usize = 4 + 2 + 8;
+
+fuzz_target!(|data: &[u8]| {
+    let mut machine = Machine::new();
+    // renders the audio the state left behind
+    machine.set_audio_sink(Box::new(NullSink));
+    machine.load_rom(&[0x12, 0x00]).unwrap();
+    let mut state = machine.save_state()[..HEADER].to_vec();
+    state.extend(data);
+
+    if machine.load_state(&state).is_ok() {
+        run(&mut machine, &[0, 0xFFFF]);
+        // a loaded machine saves and loads like any other
+        let saved = machine.save_state();
+        machine.load_state(&saved).unwrap();
+    }
+});
@@ -0,0 +1,13 @@
+#!/bin/sh
+# Seeds the corpora of the targets that take ROM bytes with the bundled ROMs,
+# and every target with the inputs that once made it panic
+cd "$(dirname "$0")" || exit 1
+for target in rom machine; do
+    mkdir -p "corpus/$target"
+    cp ../roms/*.ch8 "corpus/$target/"
+done
+for regressions in regressions/*; do
+    target=$(basename "$regressions")
+    mkdir -p "corpus/$target"
+    cp "$regressions"/* "corpus/$target/"
+done
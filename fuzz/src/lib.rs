@@ -0,0 +1,168 @@
+//! Shared setup of the fuzz targets
+//!
+//! Every target builds a [`Machine`], runs it for at most [`CYCLES`] instructions
+//! and only cares that nothing panics - errors are expected and end the run.
+//! Machines get a [`NullSink`], so the audio is rendered every frame.
+
+use arbitrary::Arbitrary;
+use chip8_rs::Machine;
+use chip8_rs::components::{audio::NullSink, instruction::Instruction, platform::Platform, quirks::{MemoryIncrement, Quirks, StackPolicy}};
+use chip8_rs::machine::UnknownOpcodePolicy;
+
+/// Instructions a run may take
+pub const CYCLES: u64 = 20_000;
+
+pub const PLATFORMS: [Platform; 3] = [Platform::Chip8, Platform::SuperChip, Platform::XoChip];
+
+/// Machine state a program starts from, any combination of it
+#[derive(Arbitrary, Debug)]
+pub struct Setup {
+    platform: u8,
+    shift: bool,
+    memory_increment: u8,
+    jump: bool,
+    vf_reset: bool,
+    clipping: bool,
+    display_wait: bool,
+    stack_depth: u8,
+    stack_policy: u8,
+    unknown_opcodes: u8,
+    speed: u8,
+    seed: u8,
+    v: [u8; 16],
+    i: u16,
+    pc: u16,
+    /// bytes written at pc, so code can sit anywhere, up to the end of memory
+    code: [u8; 4],
+    dt: u8,
+    st: u8,
+    calls: Vec<u16>,
+    hires: bool,
+    planes: u8,
+    /// keypad state of each frame, repeated
+    keys: Vec<u16>,
+}
+
+impl Setup {
+    pub fn keys(&self) -> &[u16] {
+        &self.keys
+    }
+
+    pub fn platform(&self) -> Platform {
+        PLATFORMS[self.platform as usize % PLATFORMS.len()]
+    }
+
+    /// Machine with the settings, the ROM loaded and the registers set
+    pub fn machine(&self, rom: &[u8]) -> Option<Machine> {
+        let quirks = Quirks {
+            shift : self.shift,
+            memory_increment : [MemoryIncrement::None, MemoryIncrement::X, MemoryIncrement::XPlusOne][self.memory_increment as usize % 3],
+            jump : self.jump,
+            vf_reset : self.vf_reset,
+            clipping : self.clipping,
+            display_wait : self.display_wait,
+            stack_depth : self.stack_depth as usize,
+            stack_policy : [StackPolicy::Error, StackPolicy::Trap, StackPolicy::Wrap][self.stack_policy as usize % 3],
+        };
+        let mut machine = Machine::with_quirks(quirks);
+        machine.cpu.platform = self.platform();
+        machine.cycles_per_frame = self.speed as usize;
+        machine.unknown_opcodes = [UnknownOpcodePolicy::Ignore, UnknownOpcodePolicy::Warn, UnknownOpcodePolicy::Halt, UnknownOpcodePolicy::Break][self.unknown_opcodes as usize % 4];
+        machine.set_audio_sink(Box::new(NullSink));
+        machine.load_rom(rom).ok()?;
+
+        let cpu = &mut machine.cpu;
+        cpu.random.set_seed(self.seed);
+        cpu.v = self.v;
+        cpu.i = self.i;
+        cpu.pc = self.pc;
+        let size = cpu.memory.len();
+        for (offset, byte) in self.code.iter().enumerate() {
+            cpu.memory[(self.pc as usize + offset) % size] = *byte;
+        }
+        cpu.dt = self.dt;
+        cpu.st = self.st;
+        for address in &self.calls {
+            cpu.stack.push(*address);
+        }
+        cpu.display.set_hires(self.hires);
+        cpu.display.select_planes(self.planes);
+        Some(machine)
+    }
+}
+
+/// Runs for at most [`CYCLES`] instructions, `keys` held in turn a frame each
+///
+/// Traps are dropped, as if the debugger had been left right away.
+pub fn run(machine: &mut Machine, keys: &[u16]) {
+    while machine.stats.instructions < CYCLES && !machine.cpu.exited {
+        let frame_keys = match keys {
+            [] => 0,
+            keys => keys[machine.frames as usize % keys.len()],
+        };
+        let frames = machine.frames;
+        let instructions = machine.stats.instructions;
+        if machine.run_frame(frame_keys).is_err() {
+            return;
+        }
+        machine.trap = None;
+        machine.warnings.clear();
+        // a trap on the first instruction of a frame makes no progress
+        if (machine.frames, machine.stats.instructions) == (frames, instructions) {
+            return;
+        }
+    }
+}
+
+/// Opcodes of every instruction with the operand bits cleared, and the mask of those bits
+const TEMPLATES: [(u16, u16); 50] = [
+    (0x00C0, 0x000F), (0x00D0, 0x000F), (0x00E0, 0), (0x00EE, 0), (0x00FB, 0), (0x00FC, 0), (0x00FD, 0),
+    (0x00FE, 0), (0x00FF, 0), (0x1000, 0x0FFF), (0x2000, 0x0FFF), (0x3000, 0x0FFF), (0x4000, 0x0FFF),
+    (0x5000, 0x0FF0), (0x5002, 0x0FF0), (0x5003, 0x0FF0), (0x6000, 0x0FFF), (0x7000, 0x0FFF),
+    (0x8000, 0x0FF0), (0x8001, 0x0FF0), (0x8002, 0x0FF0), (0x8003, 0x0FF0), (0x8004, 0x0FF0),
+    (0x8005, 0x0FF0), (0x8006, 0x0FF0), (0x8007, 0x0FF0), (0x800E, 0x0FF0), (0x9000, 0x0FF0),
+    (0xA000, 0x0FFF), (0xB000, 0x0FFF), (0xC000, 0x0FFF), (0xD000, 0x0FFF), (0xE09E, 0x0F00),
+    (0xE0A1, 0x0F00), (0xF000, 0), (0xF001, 0x0F00), (0xF002, 0), (0xF007, 0x0F00), (0xF00A, 0x0F00),
+    (0xF015, 0x0F00), (0xF018, 0x0F00), (0xF01E, 0x0F00), (0xF029, 0x0F00), (0xF030, 0x0F00),
+    (0xF033, 0x0F00), (0xF03A, 0x0F00), (0xF055, 0x0F00), (0xF065, 0x0F00), (0xF075, 0x0F00), (0xF085, 0x0F00),
+];
+
+/// An instruction of a generated program
+#[derive(Arbitrary, Debug)]
+pub struct Op {
+    template: u8,
+    operands: u16,
+}
+
+/// Encodes `ops` into a ROM of instructions the platform has
+///
+/// Jumps and calls land on the start of one of the instructions, so the
+/// program keeps running code rather than falling into data.
+pub fn assemble(ops: &[Op], platform: Platform) -> Vec<u8> {
+    let opcodes: Vec<(u16, &Op)> = ops.iter()
+        .map(|op| (TEMPLATES[op.template as usize % TEMPLATES.len()], op))
+        .map(|((opcode, mask), op)| (opcode | op.operands & mask, op))
+        .filter(|(opcode, _)| Instruction::decode(*opcode, platform).is_some())
+        .collect();
+
+    let mut starts = vec![];
+    let mut address = 0x200u16;
+    for (opcode, _) in &opcodes {
+        starts.push(address);
+        address = address.wrapping_add(if *opcode == 0xF000 { 4 } else { 2 });
+    }
+
+    let mut rom = vec![];
+    for (opcode, op) in &opcodes {
+        let opcode = match opcode & 0xF000 {
+            0x1000 | 0x2000 => opcode & 0xF000 | starts[op.operands as usize % starts.len()] & 0x0FFF,
+            _ => *opcode,
+        };
+        rom.extend(opcode.to_be_bytes());
+        // the address of F000 NNNN
+        if opcode == 0xF000 {
+            rom.extend(op.operands.to_be_bytes());
+        }
+    }
+    rom
+}
@@ -0,0 +1,890 @@
+//! Assembler for Octo source
+//!
+//! Understands most of the Octo language: labels (`: name`), `:const`, `:alias`,
+//! `:macro`, `:calc`, `:byte`, `:pointer`, `:org`, `:next`, `:unpack`,
+//! `:call` and `:breakpoint`, the instruction statements and the structured
+//! `if ... then`, `if ... begin ... else ... end` and `loop ... while ... again`
+//! forms. A bare label name calls it and a bare number is a data byte.
+//!
+//! As in Octo the program starts at `main`: unless `main` is the first thing
+//! placed in memory, 0x200 holds a jump to it. Labels may be used before they are
+//! defined, constants and `:calc` only see what is defined above them.
+//!
+//! Not supported yet: `:proto`, `:assert`, `:stringmode`, `:monitor`, and the
+//! functions and floating point arithmetic of `:calc`.
+
+mod calc;
+mod tokens;
+
+use std::collections::HashMap;
+
+use crate::components::platform::Platform;
+use crate::error::Chip8Error;
+use crate::rom::START_ADDRESS;
+use crate::symbols::Symbols;
+use tokens::Token;
+
+/// Macro expansions after which the assembler gives up on a recursive macro
+const MAX_EXPANSIONS: usize = 10_000;
+
+/// Result of [`assemble`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Program {
+    /// bytes to load at 0x200
+    pub rom: Vec<u8>,
+    pub symbols: Symbols,
+    /// the lowest platform that has every instruction the program uses
+    pub platform: Platform,
+}
+
+/// Assembles Octo `source` into a ROM for `target`
+///
+/// Instructions `target` lacks are errors, and so is a program that does not
+/// fit in its memory.
+pub fn assemble(source: &str, target: Platform) -> Result<Program, Chip8Error> {
+    let mut tokens = tokens::tokenize(source);
+    tokens.reverse();
+    Assembler {
+        tokens,
+        line : 1,
+        target,
+        platform : Platform::Chip8,
+        memory : vec![0; target.memory_size()],
+        here : START_ADDRESS,
+        end : START_ADDRESS,
+        labels : vec![],
+        constants : HashMap::new(),
+        aliases : HashMap::new(),
+        macros : HashMap::new(),
+        fixups : vec![],
+        branches : vec![],
+        loops : vec![],
+        breakpoints : vec![],
+        expansions : 0,
+        started : false,
+    }.run()
+}
+
+/// How a label's address goes into the program
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Slot {
+    /// the low 12 bits of the opcode at the address
+    Nnn,
+    /// the 16-bit word at the address
+    Word,
+    /// the high byte, below `nibble` (`:unpack`)
+    High { nibble: Option<u8> },
+    /// the low byte (`:unpack`)
+    Low,
+}
+
+/// Use of a label, filled in once every label is known
+struct Fixup {
+    at: usize,
+    slot: Slot,
+    name: String,
+    line: usize,
+}
+
+struct Macro {
+    arguments: Vec<String>,
+    body: Vec<Token>,
+}
+
+/// `if ... begin` waiting for its `else` or `end`
+struct Branch {
+    /// the jump over the block
+    jump: usize,
+    has_else: bool,
+    line: usize,
+}
+
+/// `loop` waiting for its `again`
+struct Loop {
+    start: usize,
+    /// the jumps of its `while`s
+    exits: Vec<usize>,
+    line: usize,
+}
+
+/// Right side of a comparison
+#[derive(Clone, Copy)]
+enum Operand {
+    Register(usize),
+    Byte(u8),
+}
+
+struct Assembler {
+    /// tokens still to read, the next one last
+    tokens: Vec<Token>,
+    /// line of the token read last
+    line: usize,
+    target: Platform,
+    platform: Platform,
+    memory: Vec<u8>,
+    here: usize,
+    /// end of the furthest byte written
+    end: usize,
+    labels: Vec<(String, u16)>,
+    constants: HashMap<String, i64>,
+    aliases: HashMap<String, usize>,
+    macros: HashMap<String, Macro>,
+    fixups: Vec<Fixup>,
+    branches: Vec<Branch>,
+    loops: Vec<Loop>,
+    breakpoints: Vec<(String, u16)>,
+    expansions: usize,
+    /// whether anything was placed in memory yet
+    started: bool,
+}
+
+impl Assembler {
+    fn run(mut self) -> Result<Program, Chip8Error> {
+        while let Some(token) = self.tokens.pop() {
+            self.line = token.line;
+            self.statement(&token.text)?;
+        }
+        self.start()?;
+
+        if let Some(branch) = self.branches.last() {
+            return Err(syntax(branch.line, "`begin` without `end`"));
+        }
+        if let Some(block) = self.loops.last() {
+            return Err(syntax(block.line, "`loop` without `again`"));
+        }
+        for fixup in std::mem::take(&mut self.fixups) {
+            let Some(address) = self.lookup(&fixup.name) else {
+                return Err(syntax(fixup.line, &format!("\"{}\" is not defined", fixup.name)));
+            };
+            self.fill(fixup.at, fixup.slot, address).map_err(|message| syntax(fixup.line, &message))?;
+        }
+
+        Ok(Program {
+            rom : self.memory[START_ADDRESS..self.end].to_vec(),
+            symbols : Symbols { labels : self.labels, breakpoints : self.breakpoints },
+            platform : self.platform,
+        })
+    }
+
+    fn statement(&mut self, token: &str) -> Result<(), Chip8Error> {
+        match token {
+            ":" => {
+                let name = self.name()?;
+                if name != "main" {
+                    self.start()?;
+                }
+                self.define(name, self.here)?;
+            }
+            ":next" => {
+                // the operand byte of the next instruction, for self-modifying code
+                let name = self.name()?;
+                self.start()?;
+                self.define(name, self.here + 1)?;
+            }
+            ":const" => {
+                let name = self.name()?;
+                let value = self.value()?;
+                self.constant(name, value)?;
+            }
+            ":calc" => {
+                let name = self.name()?;
+                self.expect("{")?;
+                let value = self.calc()?;
+                self.constant(name, value)?;
+            }
+            ":alias" => {
+                let name = self.name()?;
+                let register = self.register()?;
+                self.aliases.insert(name, register);
+            }
+            ":macro" => self.define_macro()?,
+            ":byte" => {
+                let byte = self.byte()?;
+                self.write(&[byte])?;
+            }
+            ":pointer" => {
+                self.write(&[0, 0])?;
+                self.address(self.here - 2, Slot::Word)?;
+            }
+            ":org" => {
+                self.start()?;
+                let address = self.value()?;
+                if !(START_ADDRESS as i64..self.memory.len() as i64).contains(&address) {
+                    return Err(self.error(&format!("{:#X} is outside of the program memory", address)));
+                }
+                self.here = address as usize;
+            }
+            ":unpack" => {
+                let nibble = match self.next()?.as_str() {
+                    "long" => {
+                        self.require(Platform::XoChip, "`:unpack long`")?;
+                        None
+                    }
+                    nibble => Some(self.nibble_of(nibble)?),
+                };
+                self.emit(0x6000)?;
+                self.emit(0x6100)?;
+                let at = self.here - 4;
+                let name = self.next()?;
+                self.address_of(&name, at + 1, Slot::High { nibble })?;
+                self.address_of(&name, at + 3, Slot::Low)?;
+            }
+            ":breakpoint" => {
+                let name = self.name()?;
+                self.start()?;
+                self.breakpoints.push((name, self.here as u16));
+            }
+            ":call" => self.jump(0x2000)?,
+            "clear" => self.emit(0x00E0)?,
+            "return" | ";" => self.emit(0x00EE)?,
+            "exit" => self.extended(Platform::SuperChip, token, 0x00FD)?,
+            "lores" => self.extended(Platform::SuperChip, token, 0x00FE)?,
+            "hires" => self.extended(Platform::SuperChip, token, 0x00FF)?,
+            "scroll-left" => self.extended(Platform::SuperChip, token, 0x00FC)?,
+            "scroll-right" => self.extended(Platform::SuperChip, token, 0x00FB)?,
+            "scroll-down" => {
+                let n = self.nibble()?;
+                self.extended(Platform::SuperChip, token, 0x00C0 | n as u16)?;
+            }
+            "scroll-up" => {
+                let n = self.nibble()?;
+                self.extended(Platform::XoChip, token, 0x00D0 | n as u16)?;
+            }
+            "audio" => self.extended(Platform::XoChip, token, 0xF002)?,
+            "plane" => {
+                let planes = self.nibble()?;
+                self.extended(Platform::XoChip, token, 0xF001 | (planes as u16) << 8)?;
+            }
+            "jump" => self.jump(0x1000)?,
+            "jump0" => self.jump(0xB000)?,
+            "native" => self.jump(0x0000)?,
+            "sprite" => {
+                let x = self.register()?;
+                let y = self.register()?;
+                let n = self.nibble()?;
+                if n == 0 {
+                    self.require(Platform::SuperChip, "`sprite` with 0 rows")?;
+                }
+                self.emit(0xD000 | (x as u16) << 8 | (y as u16) << 4 | n as u16)?;
+            }
+            "bcd" => self.register_op(0xF033)?,
+            "save" | "load" => {
+                let x = self.register()?;
+                if self.peek() == Some("-") {
+                    self.next()?;
+                    let y = self.register()?;
+                    let opcode = if token == "save" { 0x5002 } else { 0x5003 };
+                    self.extended(Platform::XoChip, &format!("`{} vx - vy`", token), opcode | (x as u16) << 8 | (y as u16) << 4)?;
+                } else {
+                    let opcode = if token == "save" { 0xF055 } else { 0xF065 };
+                    self.emit(opcode | (x as u16) << 8)?;
+                }
+            }
+            "saveflags" | "loadflags" => {
+                let x = self.register()?;
+                let platform = if x > 7 { Platform::XoChip } else { Platform::SuperChip };
+                let opcode = if token == "saveflags" { 0xF075 } else { 0xF085 };
+                self.extended(platform, token, opcode | (x as u16) << 8)?;
+            }
+            "i" => self.assign_i()?,
+            "delay" | "buzzer" | "pitch" => {
+                self.expect(":=")?;
+                match token {
+                    "delay" => self.register_op(0xF015)?,
+                    "buzzer" => self.register_op(0xF018)?,
+                    _ => {
+                        let x = self.register()?;
+                        self.extended(Platform::XoChip, "`pitch`", 0xF03A | (x as u16) << 8)?;
+                    }
+                }
+            }
+            "if" => {
+                let (x, comparison, operand) = self.condition()?;
+                match self.next()?.as_str() {
+                    "then" => self.skip_unless(x, &comparison, operand)?,
+                    "begin" => {
+                        self.skip_unless(x, negate(&comparison), operand)?;
+                        self.emit(0x1000)?;
+                        self.branches.push(Branch { jump : self.here - 2, has_else : false, line : self.line });
+                    }
+                    other => return Err(self.error(&format!("expected `then` or `begin`, not \"{}\"", other))),
+                }
+            }
+            "else" => {
+                let Some(branch) = self.branches.pop().filter(|branch| !branch.has_else) else {
+                    return Err(self.error("`else` without `if ... begin`"));
+                };
+                self.emit(0x1000)?;
+                self.patch(branch.jump, self.here)?;
+                self.branches.push(Branch { jump : self.here - 2, has_else : true, line : branch.line });
+            }
+            "end" => {
+                let branch = self.branches.pop().ok_or_else(|| self.error("`end` without `if ... begin`"))?;
+                self.patch(branch.jump, self.here)?;
+            }
+            "loop" => {
+                self.start()?;
+                self.loops.push(Loop { start : self.here, exits : vec![], line : self.line });
+            }
+            "while" => {
+                if self.loops.is_empty() {
+                    return Err(self.error("`while` outside of a loop"));
+                }
+                let (x, comparison, operand) = self.condition()?;
+                self.skip_unless(x, negate(&comparison), operand)?;
+                self.emit(0x1000)?;
+                let exit = self.here - 2;
+                if let Some(block) = self.loops.last_mut() {
+                    block.exits.push(exit);
+                }
+            }
+            "again" => {
+                let block = self.loops.pop().ok_or_else(|| self.error("`again` without `loop`"))?;
+                self.emit(0x1000)?;
+                self.patch(self.here - 2, block.start)?;
+                for exit in block.exits {
+                    self.patch(exit, self.here)?;
+                }
+            }
+            _ if self.macros.contains_key(token) => self.expand(token)?,
+            _ if self.register_number(token).is_some() => {
+                let x = self.register_number(token).unwrap_or_default();
+                self.assign(x)?;
+            }
+            _ if tokens::number(token).is_some() => {
+                self.tokens.push(Token { text : token.to_string(), line : self.line });
+                let byte = self.byte()?;
+                self.write(&[byte])?;
+            }
+            _ if is_name(token) => {
+                // a bare label calls it
+                self.emit(0x2000)?;
+                self.address_of(token, self.here - 2, Slot::Nnn)?;
+            }
+            _ => return Err(self.error(&format!("\"{}\" is not a statement", token))),
+        }
+        Ok(())
+    }
+
+    /// `vx op ...`
+    fn assign(&mut self, x: usize) -> Result<(), Chip8Error> {
+        let x_bits = (x as u16) << 8;
+        let operator = self.next()?;
+        let register = self.peek().and_then(|token| self.register_number(token));
+
+        match (operator.as_str(), register) {
+            (":=", _) if self.peek() == Some("key") => {
+                self.next()?;
+                self.emit(0xF00A | x_bits)
+            }
+            (":=", _) if self.peek() == Some("delay") => {
+                self.next()?;
+                self.emit(0xF007 | x_bits)
+            }
+            (":=", _) if self.peek() == Some("random") => {
+                self.next()?;
+                let mask = self.byte()?;
+                self.emit(0xC000 | x_bits | mask as u16)
+            }
+            (":=", Some(_)) => self.pair(0x8000 | x_bits),
+            (":=", None) => {
+                let byte = self.byte()?;
+                self.emit(0x6000 | x_bits | byte as u16)
+            }
+            ("+=", Some(_)) => self.pair(0x8004 | x_bits),
+            ("+=", None) => {
+                let byte = self.byte()?;
+                self.emit(0x7000 | x_bits | byte as u16)
+            }
+            ("-=", Some(_)) => self.pair(0x8005 | x_bits),
+            ("-=", None) => {
+                let byte = self.byte()?;
+                self.emit(0x7000 | x_bits | byte.wrapping_neg() as u16)
+            }
+            ("|=", _) => self.pair(0x8001 | x_bits),
+            ("&=", _) => self.pair(0x8002 | x_bits),
+            ("^=", _) => self.pair(0x8003 | x_bits),
+            (">>=", _) => self.pair(0x8006 | x_bits),
+            ("=-", _) => self.pair(0x8007 | x_bits),
+            ("<<=", _) => self.pair(0x800E | x_bits),
+            (operator, _) => Err(self.error(&format!("\"{}\" is not an operator", operator))),
+        }
+    }
+
+    /// `i op ...`
+    fn assign_i(&mut self) -> Result<(), Chip8Error> {
+        match self.next()?.as_str() {
+            "+=" => self.register_op(0xF01E),
+            ":=" => match self.peek() {
+                Some("hex") => {
+                    self.next()?;
+                    self.register_op(0xF029)
+                }
+                Some("bighex") => {
+                    self.next()?;
+                    let x = self.register()?;
+                    self.extended(Platform::SuperChip, "`bighex`", 0xF030 | (x as u16) << 8)
+                }
+                Some("long") => {
+                    self.next()?;
+                    self.require(Platform::XoChip, "`i := long`")?;
+                    self.emit(0xF000)?;
+                    self.emit(0x0000)?;
+                    self.address(self.here - 2, Slot::Word)
+                }
+                _ => self.jump(0xA000),
+            },
+            operator => Err(self.error(&format!("\"{}\" is not an operator of i", operator))),
+        }
+    }
+
+    /// `vx op vy` with the Y register still to read
+    fn pair(&mut self, opcode: u16) -> Result<(), Chip8Error> {
+        let y = self.register()?;
+        self.emit(opcode | (y as u16) << 4)
+    }
+
+    /// An instruction with a register to read into X
+    fn register_op(&mut self, opcode: u16) -> Result<(), Chip8Error> {
+        let x = self.register()?;
+        self.emit(opcode | (x as u16) << 8)
+    }
+
+    /// An instruction with an address to read into NNN
+    fn jump(&mut self, opcode: u16) -> Result<(), Chip8Error> {
+        self.emit(opcode)?;
+        self.address(self.here - 2, Slot::Nnn)
+    }
+
+    /// `vx op operand` of an `if` or a `while`
+    fn condition(&mut self) -> Result<(usize, String, Option<Operand>), Chip8Error> {
+        let x = self.register()?;
+        let comparison = self.next()?;
+        let operand = match comparison.as_str() {
+            "key" | "-key" => None,
+            "==" | "!=" | "<" | ">" | "<=" | ">=" => Some(match self.peek().and_then(|token| self.register_number(token)) {
+                Some(y) => {
+                    self.next()?;
+                    Operand::Register(y)
+                }
+                None => Operand::Byte(self.byte()?),
+            }),
+            other => return Err(self.error(&format!("\"{}\" is not a comparison", other))),
+        };
+        Ok((x, comparison, operand))
+    }
+
+    /// Emits what skips the next instruction unless the condition holds
+    ///
+    /// The ordering comparisons go through VF: `vf := a ; vf -= b` leaves VF at 1
+    /// when `a >= b`, and with an immediate `b` it is `vf := b ; vf =- a`.
+    fn skip_unless(&mut self, x: usize, comparison: &str, operand: Option<Operand>) -> Result<(), Chip8Error> {
+        let x_bits = (x as u16) << 8;
+        match (comparison, operand) {
+            ("==", Some(Operand::Byte(kk))) => self.emit(0x4000 | x_bits | kk as u16),
+            ("==", Some(Operand::Register(y))) => self.emit(0x9000 | x_bits | (y as u16) << 4),
+            ("!=", Some(Operand::Byte(kk))) => self.emit(0x3000 | x_bits | kk as u16),
+            ("!=", Some(Operand::Register(y))) => self.emit(0x5000 | x_bits | (y as u16) << 4),
+            ("key", _) => self.emit(0xE0A1 | x_bits),
+            ("-key", _) => self.emit(0xE09E | x_bits),
+            (_, Some(operand)) => {
+                // `a > b` is `b < a` and `a <= b` is `b >= a`
+                let swapped = matches!(comparison, ">" | "<=");
+                let (a, b) = if swapped { (operand, Operand::Register(x)) } else { (Operand::Register(x), operand) };
+                match (a, b) {
+                    (a, Operand::Register(b)) => {
+                        match a {
+                            Operand::Register(a) => self.emit(0x8F00 | (a as u16) << 4)?,
+                            Operand::Byte(a) => self.emit(0x6F00 | a as u16)?,
+                        }
+                        self.emit(0x8F05 | (b as u16) << 4)?;
+                    }
+                    (Operand::Register(a), Operand::Byte(b)) => {
+                        self.emit(0x6F00 | b as u16)?;
+                        self.emit(0x8F07 | (a as u16) << 4)?;
+                    }
+                    (Operand::Byte(_), Operand::Byte(_)) => unreachable!("one side is always vx"),
+                }
+                // VF is 1 when `a >= b`
+                match comparison {
+                    "<" | ">" => self.emit(0x4F00),
+                    _ => self.emit(0x3F00),
+                }
+            }
+            _ => Err(self.error(&format!("\"{}\" needs something to compare to", comparison))),
+        }
+    }
+
+    fn define_macro(&mut self) -> Result<(), Chip8Error> {
+        let name = self.name()?;
+        let mut arguments = vec![];
+        loop {
+            match self.next()?.as_str() {
+                "{" => break,
+                argument => arguments.push(argument.to_string()),
+            }
+        }
+
+        let mut body = vec![];
+        let mut depth = 1;
+        loop {
+            let token = self.tokens.pop().ok_or_else(|| self.error(&format!("macro \"{}\" has no closing brace", name)))?;
+            match token.text.as_str() {
+                "{" => depth += 1,
+                "}" if depth == 1 => break,
+                "}" => depth -= 1,
+                _ => (),
+            }
+            body.push(token);
+        }
+        self.macros.insert(name, Macro { arguments, body });
+        Ok(())
+    }
+
+    /// Replaces a macro's name and arguments with its body
+    fn expand(&mut self, name: &str) -> Result<(), Chip8Error> {
+        self.expansions += 1;
+        if self.expansions > MAX_EXPANSIONS {
+            return Err(self.error(&format!("macro \"{}\" keeps expanding", name)));
+        }
+        let count = self.macros[name].arguments.len();
+        let mut values = vec![];
+        for _ in 0..count {
+            values.push(self.next()?);
+        }
+
+        let line = self.line;
+        let definition = &self.macros[name];
+        let expansion: Vec<Token> = definition.body.iter().rev().map(|token| {
+            let text = match definition.arguments.iter().position(|argument| *argument == token.text) {
+                Some(index) => values[index].clone(),
+                None => token.text.clone(),
+            };
+            Token { text, line }
+        }).collect();
+        self.tokens.extend(expansion);
+        Ok(())
+    }
+
+    /// Reads an address into `slot` at `at`, now or once the label is defined
+    fn address(&mut self, at: usize, slot: Slot) -> Result<(), Chip8Error> {
+        let token = self.next()?;
+        self.address_of(&token, at, slot)
+    }
+
+    fn address_of(&mut self, token: &str, at: usize, slot: Slot) -> Result<(), Chip8Error> {
+        if is_name(token) && !self.constants.contains_key(token) {
+            self.fixups.push(Fixup { at, slot, name : token.to_string(), line : self.line });
+            return Ok(());
+        }
+        self.tokens.push(Token { text : token.to_string(), line : self.line });
+        let address = self.value()?;
+        self.fill(at, slot, address).map_err(|message| self.error(&message))
+    }
+
+    /// Writes `address` into the program as `slot` says
+    fn fill(&mut self, at: usize, slot: Slot, address: i64) -> Result<(), String> {
+        let max = match slot {
+            Slot::Nnn | Slot::High { nibble: Some(_) } => 0xFFF,
+            Slot::Word | Slot::High { nibble: None } | Slot::Low => 0xFFFF,
+        };
+        if !(0..=max).contains(&address) {
+            let hint = if max == 0xFFF { ", XO-CHIP's `i := long` reaches further" } else { "" };
+            return Err(format!("address {:#X} does not fit in {} bits{}", address, if max == 0xFFF { 12 } else { 16 }, hint));
+        }
+        let address = address as u16;
+        match slot {
+            Slot::Nnn => {
+                self.memory[at] = (self.memory[at] & 0xF0) | (address >> 8) as u8;
+                self.memory[at + 1] = address as u8;
+            }
+            Slot::Word => {
+                self.memory[at] = (address >> 8) as u8;
+                self.memory[at + 1] = address as u8;
+            }
+            Slot::High { nibble } => self.memory[at] = nibble.unwrap_or_default() << 4 | (address >> 8) as u8,
+            Slot::Low => self.memory[at] = address as u8,
+        }
+        Ok(())
+    }
+
+    /// Points the jump at `at` to `target`
+    fn patch(&mut self, at: usize, target: usize) -> Result<(), Chip8Error> {
+        self.fill(at, Slot::Nnn, target as i64).map_err(|message| self.error(&message))
+    }
+
+    fn emit(&mut self, opcode: u16) -> Result<(), Chip8Error> {
+        self.write(&opcode.to_be_bytes())
+    }
+
+    /// Emits an instruction `platform` introduced
+    fn extended(&mut self, platform: Platform, what: &str, opcode: u16) -> Result<(), Chip8Error> {
+        let what = if what.starts_with('`') { what.to_string() } else { format!("`{}`", what) };
+        self.require(platform, &what)?;
+        self.emit(opcode)
+    }
+
+    /// Places the jump to `main` at 0x200, unless `main` is there already
+    fn start(&mut self) -> Result<(), Chip8Error> {
+        if std::mem::replace(&mut self.started, true) || self.lookup("main") == Some(START_ADDRESS as i64) {
+            return Ok(());
+        }
+        self.emit(0x1000)?;
+        self.fixups.push(Fixup { at : START_ADDRESS, slot : Slot::Nnn, name : "main".to_string(), line : 1 });
+        Ok(())
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<(), Chip8Error> {
+        self.start()?;
+        if self.here + bytes.len() > self.memory.len() {
+            return Err(self.error(&format!("the program does not fit in the memory of {}", Platform::NAMES[self.target as usize])));
+        }
+        self.memory[self.here..self.here + bytes.len()].copy_from_slice(bytes);
+        self.here += bytes.len();
+        self.end = self.end.max(self.here);
+        Ok(())
+    }
+
+    /// Fails unless the target has what `platform` introduced
+    fn require(&mut self, platform: Platform, what: &str) -> Result<(), Chip8Error> {
+        if platform as u8 > self.target as u8 {
+            return Err(self.error(&format!("{} needs {}, the target is {}", what, Platform::NAMES[platform as usize], Platform::NAMES[self.target as usize])));
+        }
+        if platform as u8 > self.platform as u8 {
+            self.platform = platform;
+        }
+        Ok(())
+    }
+
+    fn define(&mut self, name: String, address: usize) -> Result<(), Chip8Error> {
+        if self.lookup(&name).is_some() {
+            return Err(self.error(&format!("\"{}\" is already defined", name)));
+        }
+        self.labels.push((name, address as u16));
+        Ok(())
+    }
+
+    fn constant(&mut self, name: String, value: i64) -> Result<(), Chip8Error> {
+        if self.labels.iter().any(|(label, _)| *label == name) {
+            return Err(self.error(&format!("\"{}\" is already a label", name)));
+        }
+        self.constants.insert(name, value);
+        Ok(())
+    }
+
+    /// Value of a constant or a label defined so far
+    fn lookup(&self, name: &str) -> Option<i64> {
+        if name == "HERE" {
+            return Some(self.here as i64);
+        }
+        self.constants.get(name).copied()
+            .or_else(|| self.labels.iter().find(|(label, _)| label == name).map(|(_, address)| *address as i64))
+    }
+
+    /// A number, a defined name or a `{ ... }` expression
+    fn value(&mut self) -> Result<i64, Chip8Error> {
+        let token = self.next()?;
+        if token == "{" {
+            return self.calc();
+        }
+        tokens::number(&token)
+            .or_else(|| self.lookup(&token))
+            .ok_or_else(|| self.error(&format!("\"{}\" is not a number or a defined name", token)))
+    }
+
+    /// Evaluates the tokens up to the closing brace
+    fn calc(&mut self) -> Result<i64, Chip8Error> {
+        let mut expression = vec![];
+        loop {
+            match self.next()?.as_str() {
+                "}" => break,
+                token => expression.push(token.to_string()),
+            }
+        }
+        calc::evaluate(&expression, &|name| self.lookup(name)).map_err(|message| self.error(&message))
+    }
+
+    /// A value that fits in a byte, negative ones in two's complement
+    fn byte(&mut self) -> Result<u8, Chip8Error> {
+        let value = self.value()?;
+        if !(-128..=255).contains(&value) {
+            return Err(self.error(&format!("{} does not fit in a byte", value)));
+        }
+        Ok(value as u8)
+    }
+
+    fn nibble(&mut self) -> Result<u8, Chip8Error> {
+        let token = self.next()?;
+        self.nibble_of(&token)
+    }
+
+    fn nibble_of(&mut self, token: &str) -> Result<u8, Chip8Error> {
+        self.tokens.push(Token { text : token.to_string(), line : self.line });
+        let value = self.value()?;
+        if !(0..=0xF).contains(&value) {
+            return Err(self.error(&format!("{} does not fit in 4 bits", value)));
+        }
+        Ok(value as u8)
+    }
+
+    fn register(&mut self) -> Result<usize, Chip8Error> {
+        let token = self.next()?;
+        self.register_number(&token).ok_or_else(|| self.error(&format!("expected a register, not \"{}\"", token)))
+    }
+
+    /// Number of the register `v0` to `vf` or alias `token` names
+    fn register_number(&self, token: &str) -> Option<usize> {
+        if let Some(register) = self.aliases.get(token) {
+            return Some(*register);
+        }
+        let digit = token.strip_prefix('v').or_else(|| token.strip_prefix('V'))?;
+        (digit.len() == 1).then(|| usize::from_str_radix(digit, 16).ok()).flatten()
+    }
+
+    fn name(&mut self) -> Result<String, Chip8Error> {
+        let token = self.next()?;
+        if !is_name(&token) || self.register_number(&token).is_some() {
+            return Err(self.error(&format!("\"{}\" cannot be a name", token)));
+        }
+        Ok(token)
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<(), Chip8Error> {
+        match self.next()? {
+            token if token == expected => Ok(()),
+            token => Err(self.error(&format!("expected \"{}\", not \"{}\"", expected, token))),
+        }
+    }
+
+    fn peek(&self) -> Option<&str> {
+        self.tokens.last().map(|token| token.text.as_str())
+    }
+
+    fn next(&mut self) -> Result<String, Chip8Error> {
+        let token = self.tokens.pop().ok_or_else(|| self.error("the source ends in the middle of a statement"))?;
+        self.line = token.line;
+        Ok(token.text)
+    }
+
+    fn error(&self, message: &str) -> Chip8Error {
+        syntax(self.line, message)
+    }
+}
+
+fn syntax(line: usize, message: &str) -> Chip8Error {
+    Chip8Error::Syntax { line, message : message.to_string() }
+}
+
+/// Whether `token` can name a label, constant or macro
+fn is_name(token: &str) -> bool {
+    token.starts_with(|character: char| character.is_ascii_alphabetic() || character == '_')
+        && token.chars().all(|character| character.is_ascii_alphanumeric() || matches!(character, '_' | '-'))
+}
+
+/// The comparison that holds exactly when `comparison` does not
+fn negate(comparison: &str) -> &'static str {
+    match comparison {
+        "==" => "!=",
+        "!=" => "==",
+        "<" => ">=",
+        ">=" => "<",
+        ">" => "<=",
+        "<=" => ">",
+        "key" => "-key",
+        _ => "key",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::assemble;
+    use crate::components::platform::Platform;
+    use crate::error::Chip8Error;
+
+    fn words(source: &str) -> Vec<u16> {
+        let program = assemble(source, Platform::XoChip).unwrap();
+        program.rom.chunks(2).map(|pair| u16::from_be_bytes([pair[0], pair[1]])).collect()
+    }
+
+    #[test]
+    fn it_assembles_statements() {
+        let source = "
+            : main          # the jump to main is left out
+              i := digit
+              v0 := 5  v1 += 0x10  v1 -= 1  v2 := random 0xF0
+              v0 <<= v1  i += v2  sprite v0 v1 5
+              draw  jump main
+            : draw  return
+            : digit  0xF0 0x90";
+        assert_eq!(words(source), [
+            0xA216, 0x6005, 0x7110, 0x71FF, 0xC2F0,
+            0x801E, 0xF21E, 0xD015,
+            0x2214, 0x1200,
+            0x00EE,
+            0xF090,
+        ]);
+
+        let program = assemble(": start clear : main jump start", Platform::Chip8).unwrap();
+        assert_eq!(program.rom, [0x12, 0x04, 0x00, 0xE0, 0x12, 0x02], "no jump to main");
+    }
+
+    #[test]
+    fn it_expands_control_flow() {
+        let source = "
+            : main
+              if v0 == 3 then v1 := 1
+              if v0 > v2 then v1 := 2
+              if v0 key begin
+                v1 := 3
+              else
+                v1 := 4
+              end
+              loop
+                v0 += 1
+                while v0 < 10
+              again";
+        assert_eq!(words(source), [
+            0x4003, 0x6101,
+            0x8F20, 0x8F05, 0x4F00, 0x6102,
+            0xE09E, 0x1214, 0x6103, 0x1216, 0x6104,
+            0x7001, 0x6F0A, 0x8F07, 0x3F00, 0x1222, 0x1216,
+        ]);
+    }
+
+    #[test]
+    fn it_expands_macros_and_constants() {
+        let source = "
+            :const SPEED 3
+            :alias player v4
+            :calc WIDTH { 1 + SPEED * 2 }
+            :macro move register amount { register += amount }
+            : main
+              move player SPEED
+              player := WIDTH
+              :unpack 0xA data
+              :next target  v0 := 0
+            : data  :byte { HERE >> 4 } 0";
+        assert_eq!(words(source), [0x7403, 0x6407, 0x60A2, 0x610A, 0x6000, 0x2000]);
+
+        let program = assemble(source, Platform::Chip8).unwrap();
+        assert_eq!(program.symbols.address("target"), Some(0x209), "`:next` not on the operand");
+        assert_eq!(program.symbols.address("data"), Some(0x20A));
+        let program = assemble(": main :breakpoint check clear", Platform::Chip8).unwrap();
+        assert_eq!(program.symbols.breakpoints, [("check".to_string(), 0x200)], "breakpoint not in the symbols");
+    }
+
+    #[test]
+    fn it_checks_the_target() {
+        let program = assemble(": main hires i := long data : data", Platform::XoChip).unwrap();
+        assert_eq!(program.platform, Platform::XoChip, "XO-CHIP program not recognized");
+        assert_eq!(program.rom, [0x00, 0xFF, 0xF0, 0x00, 0x02, 0x06]);
+        assert_eq!(assemble(": main hires", Platform::XoChip).unwrap().platform, Platform::SuperChip);
+
+        assert!(matches!(assemble(": main\nhires", Platform::Chip8), Err(Chip8Error::Syntax { line: 2, .. })), "SUPER-CHIP instruction on CHIP-8");
+        assert!(matches!(assemble(": main\n\njump nowhere", Platform::Chip8), Err(Chip8Error::Syntax { line: 3, .. })), "undefined label");
+        assert!(matches!(assemble("clear", Platform::Chip8), Err(Chip8Error::Syntax { .. })), "no main");
+        assert!(matches!(assemble(": main if v0 == 1 begin clear", Platform::Chip8), Err(Chip8Error::Syntax { .. })), "unclosed begin");
+        assert!(matches!(assemble(":macro loop-forever { loop-forever } : main loop-forever", Platform::Chip8), Err(Chip8Error::Syntax { .. })), "recursive macro");
+        assert!(matches!(assemble(": main :org 0xFFF clear", Platform::Chip8), Err(Chip8Error::Syntax { .. })), "written past memory");
+    }
+}
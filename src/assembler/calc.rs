@@ -0,0 +1,100 @@
+//! `:calc` expressions
+//!
+//! As in Octo, binary operators have no precedence and are evaluated right to
+//! left, so `2 * 3 + 1` is `2 * (3 + 1)`: group with parentheses for anything
+//! else. The operators are `|`, `^`, `&`, `<<`, `>>`, `+`, `-`, `*`, `/`, `%` and
+//! the unary `-` and `~`, which apply to the operand right after them.
+//! Names are looked up as constants or labels defined before the expression.
+//!
+//! Unlike Octo the arithmetic is on integers, `/` rounds towards zero, and the
+//! functions (`sin`, `min`, `strlen`, ...) and comparisons are not supported.
+
+/// Binary operators
+const OPERATORS: [&str; 10] = ["|", "^", "&", "<<", ">>", "+", "-", "*", "/", "%"];
+
+/// Evaluates `tokens`, `lookup` gives the value of a name
+pub fn evaluate(tokens: &[String], lookup: &dyn Fn(&str) -> Option<i64>) -> Result<i64, String> {
+    let mut parser = Parser { tokens, position : 0, lookup };
+    let value = parser.expression()?;
+    match tokens.get(parser.position) {
+        Some(token) => Err(format!("unexpected \"{}\" in the expression", token)),
+        None => Ok(value),
+    }
+}
+
+struct Parser<'a> {
+    tokens: &'a [String],
+    position: usize,
+    lookup: &'a dyn Fn(&str) -> Option<i64>,
+}
+
+impl Parser<'_> {
+    fn next(&mut self) -> Option<&str> {
+        let token = self.tokens.get(self.position)?;
+        self.position += 1;
+        Some(token)
+    }
+
+    /// An operand, then an operator and the whole rest of the expression
+    fn expression(&mut self) -> Result<i64, String> {
+        let value = self.unary()?;
+        let Some(operator) = self.tokens.get(self.position).filter(|token| OPERATORS.contains(&token.as_str())) else {
+            return Ok(value);
+        };
+        self.position += 1;
+        let operand = self.expression()?;
+        Ok(match operator.as_str() {
+            "|" => value | operand,
+            "^" => value ^ operand,
+            "&" => value & operand,
+            "<<" => value.checked_shl(operand as u32).unwrap_or(0),
+            ">>" => value.checked_shr(operand as u32).unwrap_or(0),
+            "+" => value.wrapping_add(operand),
+            "-" => value.wrapping_sub(operand),
+            "*" => value.wrapping_mul(operand),
+            "/" => value.checked_div(operand).ok_or("division by zero")?,
+            _ => value.checked_rem(operand).ok_or("division by zero")?,
+        })
+    }
+
+    fn unary(&mut self) -> Result<i64, String> {
+        let token = self.next().map(str::to_string);
+        match token.as_deref() {
+            Some("-") => Ok(self.unary()?.wrapping_neg()),
+            Some("~") => Ok(!self.unary()?),
+            Some("(") => {
+                let value = self.expression()?;
+                match self.next() {
+                    Some(")") => Ok(value),
+                    _ => Err("missing \")\"".to_string()),
+                }
+            }
+            Some(token) => super::tokens::number(token)
+                .or_else(|| (self.lookup)(token))
+                .ok_or(format!("\"{}\" is not a number or a defined name", token)),
+            None => Err("the expression ends early".to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::evaluate;
+
+    fn calc(expression: &str) -> Result<i64, String> {
+        let tokens: Vec<String> = expression.split_whitespace().map(str::to_string).collect();
+        evaluate(&tokens, &|name| (name == "WIDTH").then_some(64))
+    }
+
+    #[test]
+    fn it_evaluates_right_to_left() {
+        assert_eq!(calc("2 * 3 + 1"), Ok(8), "precedence applied");
+        assert_eq!(calc("( 2 * 3 ) + 1"), Ok(7));
+        assert_eq!(calc("WIDTH - 2 - 1"), Ok(63), "evaluated left to right");
+        assert_eq!(calc("( ( WIDTH / 2 ) - 0x4 ) | 1 << 8"), Ok(0x11C));
+        assert_eq!(calc("- 3 + ~ 0"), Ok(-4));
+        assert!(calc("1 / 0").is_err(), "divided by zero");
+        assert!(calc("1 + HEIGHT").is_err(), "undefined name evaluated");
+        assert!(calc("1 2").is_err(), "trailing number ignored");
+    }
+}
@@ -0,0 +1,51 @@
+//! Splits Octo source into tokens
+
+/// A word of the source with the line it is on
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Token {
+    pub text: String,
+    pub line: usize,
+}
+
+/// Tokens are separated by whitespace, `#` comments run to the end of the line
+/// and braces and parentheses are tokens of their own.
+pub fn tokenize(source: &str) -> Vec<Token> {
+    let mut tokens = vec![];
+    for (index, line) in source.lines().enumerate() {
+        let code = line.split('#').next().unwrap_or_default();
+        for word in code.split_whitespace() {
+            let mut start = 0;
+            for (offset, character) in word.char_indices() {
+                if matches!(character, '{' | '}' | '(' | ')') {
+                    if start < offset {
+                        tokens.push(Token { text : word[start..offset].to_string(), line : index + 1 });
+                    }
+                    tokens.push(Token { text : character.to_string(), line : index + 1 });
+                    start = offset + 1;
+                }
+            }
+            if start < word.len() {
+                tokens.push(Token { text : word[start..].to_string(), line : index + 1 });
+            }
+        }
+    }
+    tokens
+}
+
+/// Value of a decimal, `0x` hexadecimal or `0b` binary literal, optionally negative
+pub fn number(text: &str) -> Option<i64> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text),
+    };
+    let value = if let Some(hex) = digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X")) {
+        i64::from_str_radix(hex, 16).ok()?
+    } else if let Some(binary) = digits.strip_prefix("0b").or_else(|| digits.strip_prefix("0B")) {
+        i64::from_str_radix(binary, 2).ok()?
+    } else if digits.starts_with(|character: char| character.is_ascii_digit()) {
+        digits.parse().ok()?
+    } else {
+        return None;
+    };
+    Some(if negative { -value } else { value })
+}
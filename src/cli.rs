@@ -0,0 +1,271 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use chip8_rs::components::{platform::Platform, quirks::{Quirks, StackPolicy}, stack::MAX_DEPTH};
+use chip8_rs::machine::{ReloadMode, UnknownOpcodePolicy};
+use chip8_rs::screenshot::Format;
+use clap::{error::ErrorKind, Args, CommandFactory, Parser, Subcommand, ValueEnum};
+
+#[derive(Parser)]
+#[command(name = "chip8-rs", version, about = "CHIP-8, SUPER-CHIP and XO-CHIP emulator")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// config file to use instead of the one in the config directory
+    #[arg(long, global = true, env = "CHIP8_CONFIG")]
+    pub config: Option<PathBuf>,
+
+    /// Options of `run`, which is the default command
+    #[command(flatten)]
+    pub run: RunArgs,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Run a ROM or Octo source - without a path one is picked from the ROM directory
+    Run(RunArgs),
+    /// Assemble Octo source into a ROM and a symbol map next to it
+    Assemble {
+        source: PathBuf,
+        /// where the ROM goes, the source with a .ch8 extension by default
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// platform to assemble for, the instructions of the others are errors
+        #[arg(long, value_parser = parse_platform, default_value = "xochip")]
+        platform: Platform,
+    },
+    /// Disassemble a ROM, telling code from data by following its jumps and calls
+    Disasm {
+        rom: PathBuf,
+        /// platform whose instructions to decode (guessed from the opcodes by default)
+        #[arg(long, value_parser = parse_platform)]
+        platform: Option<Platform>,
+    },
+    /// Print the size, hash and likely platform of a ROM
+    Info {
+        rom: PathBuf,
+    },
+    /// Run a ROM headlessly and print its final screen, for CI
+    ///
+    /// Exits with 3 when the emulation stops with an error and 4 when a budget runs out.
+    Test {
+        rom: PathBuf,
+        #[command(flatten)]
+        headless: HeadlessArgs,
+        #[arg(long, value_parser = parse_platform)]
+        platform: Option<Platform>,
+        #[arg(long, value_parser = parse_quirks)]
+        quirks: Option<Quirks>,
+        /// instructions per frame
+        #[arg(long)]
+        speed: Option<usize>,
+        /// key script replayed as the keypad
+        #[arg(long)]
+        input: Option<PathBuf>,
+    },
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Frontend {
+    #[default]
+    Window,
+    Terminal,
+    Headless,
+}
+
+#[derive(Args, Default)]
+pub struct RunArgs {
+    /// ROM to run, skips the selection menu - .8o files are assembled first
+    pub rom: Option<PathBuf>,
+
+    /// where the picture goes
+    #[arg(long, value_enum, default_value_t = Frontend::Window)]
+    pub frontend: Frontend,
+
+    /// chip8, schip or xochip
+    #[arg(long, env = "CHIP8_PLATFORM", value_parser = parse_platform)]
+    pub platform: Option<Platform>,
+
+    /// quirk profile: chip8, chip48, schip or xochip (defaults to the platform's)
+    #[arg(long, env = "CHIP8_QUIRKS", value_parser = parse_quirks)]
+    pub quirks: Option<Quirks>,
+
+    /// instructions per 60 Hz frame
+    #[arg(long, env = "CHIP8_SPEED")]
+    pub speed: Option<usize>,
+
+    /// call stack levels (defaults to the quirk profile's)
+    #[arg(long, value_parser = parse_stack_depth)]
+    pub stack_depth: Option<usize>,
+
+    /// what stack overflows and underflows do: error, trap (into the debugger) or wrap
+    #[arg(long, value_parser = parse_stack_policy)]
+    pub stack_policy: Option<StackPolicy>,
+
+    /// what to do with unknown opcodes: ignore, warn, halt or break (into the debugger)
+    #[arg(long, env = "CHIP8_UNKNOWN_OPCODES", value_parser = parse_policy)]
+    pub unknown_opcodes: Option<UnknownOpcodePolicy>,
+
+    /// start paused in the debugger, commands are typed into the terminal
+    #[arg(long)]
+    pub debug: bool,
+
+    /// symbol map written by `assemble`, for the debugger's labels and breakpoints
+    #[arg(long)]
+    pub symbols: Option<PathBuf>,
+
+    /// draw on the window with the mouse
+    #[arg(long)]
+    pub draw: bool,
+
+    /// do not pace the frames to 60 Hz
+    #[arg(long)]
+    pub fast: bool,
+
+    /// memory for rewinding in MiB, 0 disables it
+    #[arg(long, env = "CHIP8_REWIND")]
+    pub rewind: Option<usize>,
+
+    /// save the state on quit and resume from it on the next launch
+    #[arg(long)]
+    pub autosave: bool,
+
+    /// run the terminal frontend in an alternate screen
+    #[arg(long)]
+    pub alternate_screen: bool,
+
+    /// reload the ROM, or the source it was assembled from, whenever the file changes
+    #[arg(long, conflicts_with_all = ["record", "play"])]
+    pub watch: bool,
+
+    /// what a reload keeps: reset starts over, keep leaves the registers and display alone
+    #[arg(long, env = "CHIP8_RELOAD", value_parser = parse_reload)]
+    pub reload: Option<ReloadMode>,
+
+    #[command(flatten, next_help_heading = "Headless frontend")]
+    pub headless: HeadlessArgs,
+
+    /// record the keys of every frame into a movie
+    #[arg(long, value_name = "MOVIE")]
+    pub record: Option<PathBuf>,
+
+    /// play a movie back, reporting the first frame that turns out differently
+    #[arg(long, value_name = "MOVIE", conflicts_with = "record")]
+    pub play: Option<PathBuf>,
+
+    /// start the random numbers from this position of the table
+    #[arg(long, env = "CHIP8_SEED")]
+    pub seed: Option<u8>,
+
+    /// key script replayed instead of the keyboard
+    #[arg(long, env = "CHIP8_INPUT")]
+    pub input: Option<PathBuf>,
+
+    /// record the audio into a WAV file instead of playing it
+    #[arg(long, env = "CHIP8_WAV")]
+    pub wav: Option<PathBuf>,
+
+    /// beep frequency in Hz
+    #[arg(long, env = "CHIP8_BEEP", value_parser = parse_beep)]
+    pub beep: Option<f64>,
+
+    /// beep volume, from 0.0 to 1.0
+    #[arg(long, env = "CHIP8_VOLUME")]
+    pub volume: Option<f32>,
+
+    /// directory the selection menu lists, instead of the configured ones
+    #[arg(long)]
+    pub rom_dir: Option<PathBuf>,
+}
+
+impl RunArgs {
+    /// Rules out the combinations clap cannot, with a usage error like its own
+    pub fn check(&self) -> Result<(), clap::Error> {
+        if self.debug && self.frontend == Frontend::Headless {
+            return Err(Cli::command().error(ErrorKind::ArgumentConflict,
+                "--debug cannot be used with --frontend headless, which has no debugger prompt"));
+        }
+        Ok(())
+    }
+}
+
+/// How long a headless run goes on and where its final screen goes
+#[derive(Args, Default)]
+pub struct HeadlessArgs {
+    /// frames to run for (600, or 300 for `test`)
+    #[arg(long)]
+    pub frames: Option<u64>,
+
+    /// instructions to run for, instead of frames
+    #[arg(long, conflicts_with = "frames")]
+    pub instructions: Option<u64>,
+
+    /// stop early once the program exits or jumps to itself, without a length it runs until then
+    #[arg(long)]
+    pub until_halt: bool,
+
+    /// wall-clock seconds before the run fails
+    #[arg(long, value_name = "SECONDS", value_parser = parse_seconds)]
+    pub timeout: Option<Duration>,
+
+    /// instructions before the run fails
+    #[arg(long)]
+    pub max_instructions: Option<u64>,
+
+    /// write the final screen into a file instead of printing it
+    #[arg(long)]
+    pub dump: Option<PathBuf>,
+
+    /// png, pbm or ascii - guessed from the --dump extension by default
+    #[arg(long, value_parser = parse_format)]
+    pub format: Option<Format>,
+}
+
+fn parse_platform(name: &str) -> Result<Platform, String> {
+    Platform::from_name(name)
+        .ok_or(format!("expected one of {:?}", Platform::NAMES))
+}
+
+fn parse_policy(name: &str) -> Result<UnknownOpcodePolicy, String> {
+    UnknownOpcodePolicy::from_name(name)
+        .ok_or(format!("expected one of {:?}", UnknownOpcodePolicy::NAMES))
+}
+
+fn parse_stack_policy(name: &str) -> Result<StackPolicy, String> {
+    StackPolicy::from_name(name)
+        .ok_or(format!("expected one of {:?}", StackPolicy::NAMES))
+}
+
+fn parse_stack_depth(depth: &str) -> Result<usize, String> {
+    depth.parse().ok()
+        .filter(|depth| (1..=MAX_DEPTH).contains(depth))
+        .ok_or(format!("expected a number of levels from 1 to {}", MAX_DEPTH))
+}
+
+fn parse_beep(frequency: &str) -> Result<f64, String> {
+    frequency.parse().ok()
+        .filter(|frequency: &f64| frequency.is_finite() && *frequency > 0.0)
+        .ok_or("expected a frequency in Hz above 0".to_string())
+}
+
+fn parse_reload(name: &str) -> Result<ReloadMode, String> {
+    ReloadMode::from_name(name)
+        .ok_or(format!("expected one of {:?}", ReloadMode::NAMES))
+}
+
+fn parse_format(name: &str) -> Result<Format, String> {
+    Format::from_name(name)
+        .ok_or(format!("expected one of {:?}", Format::NAMES))
+}
+
+fn parse_seconds(seconds: &str) -> Result<Duration, String> {
+    seconds.parse().ok()
+        .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+        .ok_or("expected a number of seconds".to_string())
+}
+
+fn parse_quirks(name: &str) -> Result<Quirks, String> {
+    Quirks::preset(name)
+        .ok_or(format!("expected one of {:?}", Quirks::PRESETS))
+}
@@ -0,0 +1,32 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Paces a loop to a fixed number of iterations per second
+pub struct Clock {
+    frame_duration: Duration,
+    next_frame: Instant,
+}
+
+impl Clock {
+    pub fn new(frame_rate: u32) -> Self {
+        Clock {
+            frame_duration: Duration::from_secs(1) / frame_rate,
+            next_frame: Instant::now(),
+        }
+    }
+
+    /// Sleeps until the next frame is due
+    ///
+    /// When the loop has fallen more than a frame behind the schedule is reset
+    /// rather than running a burst of frames to catch up.
+    pub fn wait(&mut self) {
+        self.next_frame += self.frame_duration;
+
+        let now = Instant::now();
+        if self.next_frame > now {
+            thread::sleep(self.next_frame - now);
+        } else if now - self.next_frame > self.frame_duration {
+            self.next_frame = now;
+        }
+    }
+}
@@ -1,4 +1,10 @@
+pub mod audio;
 pub mod cpu;
 pub mod display;
+pub mod input;
+pub mod instruction;
 pub mod keypad;
-pub mod rand;
\ No newline at end of file
+pub mod platform;
+pub mod quirks;
+pub mod rand;
+pub mod stack;
@@ -0,0 +1,279 @@
+use std::cell::RefCell;
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::rc::Rc;
+
+use crate::error::Chip8Error;
+use crate::state::{invalid, StateReader, StateWriter};
+
+/// Default output sample rate in Hz
+pub const SAMPLE_RATE: u32 = 44_100;
+/// Rate the pattern bits are played at when the pitch is at its default of 64
+pub const BASE_PLAYBACK_RATE: f64 = 4000.0;
+/// Default frequency of the square-wave beep in Hz
+pub const BEEP_FREQUENCY: f64 = 440.0;
+/// Default amplitude, 1.0 is full scale
+pub const VOLUME: f32 = 0.5;
+
+/// Destination of the rendered PCM stream - mono, samples in the -1.0..=1.0 range
+pub trait AudioSink {
+    fn write(&mut self, samples: &[f32]);
+}
+
+/// Discards everything
+pub struct NullSink;
+
+impl AudioSink for NullSink {
+    fn write(&mut self, _samples: &[f32]) {}
+}
+
+/// Collects the samples in memory
+impl AudioSink for Vec<f32> {
+    fn write(&mut self, samples: &[f32]) {
+        self.extend_from_slice(samples);
+    }
+}
+
+/// Shared sink, so the samples can be inspected while the machine owns it
+///
+/// ```
+/// use std::{cell::RefCell, rc::Rc};
+/// use chip8_rs::Machine;
+///
+/// let capture = Rc::new(RefCell::new(Vec::<f32>::new()));
+/// let mut machine = Machine::new();
+/// machine.set_audio_sink(Box::new(Rc::clone(&capture)));
+///
+/// // loop forever
+/// machine.load_rom(&[0x12, 0x00])?;
+/// machine.run_frame(0)?;
+/// assert_eq!(capture.borrow().len(), 735);
+/// # Ok::<(), chip8_rs::Chip8Error>(())
+/// ```
+impl<T: AudioSink> AudioSink for Rc<RefCell<T>> {
+    fn write(&mut self, samples: &[f32]) {
+        self.borrow_mut().write(samples);
+    }
+}
+
+/// Sound played while the sound timer is non-zero
+///
+/// A square-wave beep, unless an XO-CHIP program has loaded its own
+/// 128-bit pattern, which is then looped at a pitch-controlled rate.
+pub struct Audio {
+    /// XO-CHIP 1-bit samples, most significant bit first (F002)
+    pub pattern: Option<[u8; 16]>,
+    /// playback pitch (FX3A), 64 plays 4000 bits per second
+    pub pitch: u8,
+    /// square-wave beep frequency in Hz
+    pub beep_frequency: f64,
+    /// amplitude, 1.0 is full scale
+    pub volume: f32,
+    /// output sample rate in Hz
+    pub sample_rate: u32,
+    /// position within the pattern in bits, or within the beep period
+    position: f64,
+}
+
+impl Default for Audio {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Audio {
+    pub fn new() -> Self {
+        Audio {
+            pattern : None,
+            pitch : 64,
+            beep_frequency : BEEP_FREQUENCY,
+            volume : VOLUME,
+            sample_rate : SAMPLE_RATE,
+            position : 0.0,
+        }
+    }
+
+    /// Only the program's side, the output settings stay
+    pub(crate) fn save_state(&self, state: &mut StateWriter) {
+        state.bool(self.pattern.is_some());
+        state.array(&self.pattern.unwrap_or_default());
+        state.u8(self.pitch);
+        state.u64(self.position.to_bits());
+    }
+
+    pub(crate) fn load_state(&mut self, state: &mut StateReader) -> Result<(), Chip8Error> {
+        let has_pattern = state.bool()?;
+        let pattern = state.array()?;
+        let pitch = state.u8()?;
+        let position = f64::from_bits(state.u64()?);
+        // render indexes the pattern with it
+        let length = if has_pattern { 128.0 } else { 1.0 };
+        if !(0.0..length).contains(&position) {
+            return Err(invalid(&format!("audio position {} is out of range", position)));
+        }
+        self.pattern = has_pattern.then_some(pattern);
+        self.pitch = pitch;
+        self.position = position;
+        Ok(())
+    }
+
+    pub fn reset(&mut self) {
+        self.pattern = None;
+        self.pitch = 64;
+        self.position = 0.0;
+    }
+
+    /// Pattern bits played per second
+    pub fn playback_rate(&self) -> f64 {
+        BASE_PLAYBACK_RATE * 2f64.powf((self.pitch as f64 - 64.0) / 48.0)
+    }
+
+    /// Renders `count` samples, silence unless `playing` (sound timer non-zero)
+    pub fn render(&mut self, playing: bool, count: usize) -> Vec<f32> {
+        if !playing {
+            return vec![0.0; count];
+        }
+
+        // the pattern is 128 bits long, the beep period is 1
+        let (step, length) = match self.pattern {
+            Some(_) => (self.playback_rate() / self.sample_rate as f64, 128.0),
+            None => (self.beep_frequency / self.sample_rate as f64, 1.0),
+        };
+
+        (0..count).map(|_| {
+            let high = match &self.pattern {
+                Some(pattern) => {
+                    let bit = self.position as usize;
+                    pattern[bit / 8] & (0x80 >> (bit % 8)) != 0
+                }
+                None => self.position < 0.5,
+            };
+            self.position = (self.position + step).rem_euclid(length);
+            if high { self.volume } else { -self.volume }
+        }).collect()
+    }
+}
+
+/// Writes 16-bit mono PCM into a WAV file, sizes are patched in on drop
+pub struct WavSink {
+    writer: BufWriter<File>,
+    samples: u32,
+}
+
+impl WavSink {
+    pub fn create(path: impl AsRef<Path>, sample_rate: u32) -> io::Result<Self> {
+        let mut writer = BufWriter::new(File::create(path)?);
+
+        writer.write_all(b"RIFF")?;
+        writer.write_all(&36u32.to_le_bytes())?;
+        writer.write_all(b"WAVEfmt ")?;
+        writer.write_all(&16u32.to_le_bytes())?;
+        // PCM, mono
+        writer.write_all(&1u16.to_le_bytes())?;
+        writer.write_all(&1u16.to_le_bytes())?;
+        writer.write_all(&sample_rate.to_le_bytes())?;
+        // byte rate, block align, bits per sample
+        writer.write_all(&(sample_rate * 2).to_le_bytes())?;
+        writer.write_all(&2u16.to_le_bytes())?;
+        writer.write_all(&16u16.to_le_bytes())?;
+        writer.write_all(b"data")?;
+        writer.write_all(&0u32.to_le_bytes())?;
+
+        Ok(WavSink { writer, samples: 0 })
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        let data_size = self.samples * 2;
+        self.writer.seek(SeekFrom::Start(4))?;
+        self.writer.write_all(&(36 + data_size).to_le_bytes())?;
+        self.writer.seek(SeekFrom::Start(40))?;
+        self.writer.write_all(&data_size.to_le_bytes())?;
+        self.writer.flush()
+    }
+}
+
+impl AudioSink for WavSink {
+    fn write(&mut self, samples: &[f32]) {
+        for sample in samples {
+            let pcm = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+            if self.writer.write_all(&pcm.to_le_bytes()).is_ok() {
+                self.samples += 1;
+            }
+        }
+    }
+}
+
+impl Drop for WavSink {
+    fn drop(&mut self) {
+        let _ = self.finish();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Audio;
+    use crate::state::{StateReader, StateWriter};
+
+    #[test]
+    fn it_plays_the_pattern() {
+        let mut audio = Audio::new();
+        let mut pattern = [0; 16];
+        pattern[0] = 0b1010_0000;
+        audio.pattern = Some(pattern);
+        audio.volume = 1.0;
+        // one pattern bit per sample
+        audio.sample_rate = 4000;
+
+        assert_eq!(audio.render(false, 2), vec![0.0, 0.0], "plays while the timer is off");
+        assert_eq!(audio.render(true, 4), vec![1.0, -1.0, 1.0, -1.0], "wrong pattern played");
+    }
+
+    #[test]
+    fn it_beeps_a_square_wave() {
+        let mut audio = Audio::new();
+        audio.beep_frequency = 1000.0;
+        audio.volume = 0.25;
+        audio.sample_rate = 4000;
+
+        assert_eq!(audio.render(true, 6), vec![0.25, 0.25, -0.25, -0.25, 0.25, 0.25], "wrong beep played");
+
+        // a library user may set any frequency, the position stays within the period
+        audio.beep_frequency = -1000.0;
+        audio.render(true, 3);
+        assert!((0.0..1.0).contains(&audio.position), "position left the period: {}", audio.position);
+    }
+
+    #[test]
+    fn it_follows_the_pitch() {
+        let mut audio = Audio::new();
+        assert_eq!(audio.playback_rate(), 4000.0, "wrong default rate");
+
+        audio.pitch = 112;
+        assert_eq!(audio.playback_rate(), 8000.0, "pitch does not double the rate");
+    }
+
+    #[test]
+    fn it_rejects_positions_outside_the_pattern() {
+        let mut audio = Audio::new();
+        // saves the audio with its position replaced and loads it back
+        let reload = |audio: &mut Audio, position: f64| {
+            let mut state = StateWriter::default();
+            audio.save_state(&mut state);
+            let mut bytes = state.finish();
+            let at = bytes.len() - 8;
+            bytes[at..].copy_from_slice(&position.to_bits().to_le_bytes());
+            audio.load_state(&mut StateReader::new(&bytes))
+        };
+
+        assert!(reload(&mut audio, 0.5).is_ok(), "beep position rejected");
+        for position in [1.0, -0.5, f64::NAN, f64::INFINITY] {
+            assert!(reload(&mut audio, position).is_err(), "beep position {} loaded", position);
+        }
+
+        audio.pattern = Some([0; 16]);
+        assert!(reload(&mut audio, 127.5).is_ok(), "pattern position rejected");
+        assert!(reload(&mut audio, 128.0).is_err(), "position past the pattern loaded");
+        assert!(audio.pattern.is_some(), "pattern changed by a rejected state");
+    }
+}
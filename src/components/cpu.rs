@@ -1,38 +1,52 @@
-use std::io::Write;
+use std::ops::Range;
 
-use crossterm::cursor::MoveTo;
-use crossterm::style::{PrintStyledContent, StyledContent, Stylize};
-
-use super::display::{Display, FONT_SET, WIDTH, HEIGHT};
+use crate::error::Chip8Error;
+use crate::state::{invalid, StateReader, StateWriter};
+use super::audio::Audio;
+use super::display::{Display, FONT_SET, BIG_FONT_SET, BIG_FONT_ADDRESS};
+use super::instruction::Instruction;
 use super::keypad::Keypad;
+use super::platform::Platform;
+use super::quirks::{MemoryIncrement, Quirks, StackPolicy};
 use super::rand::DoomRNG;
+use super::stack::Stack;
 
 pub struct Cpu {
     /// index register
     pub i: u16,
     /// program counter
     pub pc: u16,
-    /// memory
-    pub memory: [u8; 4096],
+    /// memory, `platform.memory_size()` bytes
+    pub memory: Vec<u8>,
     /// registers
     pub v: [u8; 16],
-    /// stack
-    pub stack: Vec<u16>,
+    /// return addresses, `quirks.stack_depth` of them at most
+    pub stack: Stack,
     /// delay timer
     pub dt: u8,
     /// sound timer
     pub st: u8,
+    /// XO-CHIP audio pattern and pitch
+    pub audio: Audio,
     /// display
     pub display: Display,
     /// keypad
     pub keypad: Keypad,
     /// random number generator - DOOM implementation
     pub random: DoomRNG,
+    /// dialect - decides the memory size and the extended opcodes
+    pub platform: Platform,
+    /// platform behaviour of the ambiguous instructions
+    pub quirks: Quirks,
+    /// SUPER-CHIP RPL user flags (FX75/FX85)
+    pub rpl: [u8; 16],
+    /// the program has executed the 00FD exit instruction
+    pub exited: bool,
 }
 
-fn read_opcode(memory: [u8; 4096], index: u16) -> u16 {
+fn read_opcode(memory: &[u8], index: u16) -> u16 {
     (memory[index as usize] as u16) << 8 |
-        memory[(index + 1) as usize] as u16
+        memory[index as usize + 1] as u16
 }
 
 impl Default for Cpu {
@@ -46,130 +60,209 @@ impl Cpu {
         Cpu {
             i : 0,
             pc : 0,
-            memory : [0; 4096],
+            memory : vec![0; Platform::default().memory_size()],
             v : [0; 16],
-            stack : vec![],
+            stack : Stack::new(Quirks::default().stack_depth),
             dt: 0,
             st: 0,
+            audio : Audio::new(),
             display : Display::new(),
             keypad : Keypad::new(),
-            random : DoomRNG::new()
+            random : DoomRNG::new(),
+            platform : Platform::default(),
+            quirks : Quirks::default(),
+            rpl : [0; 16],
+            exited : false,
         }
     }
     
     pub fn reset(&mut self) {
         self.i = 0;
         self.pc = 512;
-        self.memory = [0; 4096];
+        self.memory = vec![0; self.platform.memory_size()];
         self.v = [0; 16];
-        self.stack = vec![];
+        self.stack = Stack::new(self.quirks.stack_depth);
         self.dt = 0;
         self.st = 0;
-        self.display.cls();
+        self.audio.reset();
+        self.keypad.reset();
+        self.exited = false;
+        self.display.set_hires(false);
+        self.display.select_planes(1);
 
-        // load the font
+        // load the fonts
         for (i, item) in FONT_SET.iter().enumerate() {
             self.memory[i] = *item;
         }
+        for (i, item) in BIG_FONT_SET.iter().enumerate() {
+            self.memory[BIG_FONT_ADDRESS + i] = *item;
+        }
     } 
 
-    pub fn load_program(&mut self, program: Vec<u8>) {
+    pub fn load_program(&mut self, program: Vec<u8>) -> Result<(), Chip8Error> {
+        let max = self.memory.len() - 512;
+        if program.len() > max {
+            return Err(Chip8Error::RomTooLarge { size: program.len(), max });
+        }
         self.memory[512..(program.len() + 512)].copy_from_slice(&program[..]);
+        Ok(())
     }
 
-    pub fn execute_cycle(&mut self) {
-        let opcode = read_opcode(self.memory, self.pc);
-        self.pc += 2;
-        let (_operation_code, _operation_type)  = self.process_opcode(opcode);
-
-        if cfg!(all(feature = "debug", not(feature = "alternate-screen"))) {
-            todo!("Cannot borrow the Cpu struct twice!");
-            // Self::display_debug(cpu, operation_code, operation_type);
+    /// Executes one instruction and returns its opcode with the operation type
+    ///
+    /// On error the pc is left on the offending instruction.
+    pub fn execute_cycle(&mut self) -> Result<(u16, &'static str), Chip8Error> {
+        let pc = self.pc;
+        if pc as usize + 1 >= self.memory.len() {
+            return Err(Chip8Error::PcOutOfBounds { pc });
         }
 
-        // decrease both timers
-        if self.st > 0 { self.st >>= 1 };
-        if self.dt > 0 { self.dt >>= 1 };
+        let opcode = read_opcode(&self.memory, pc);
+        self.pc = pc.wrapping_add(2);
+        let result = self.process_opcode(opcode);
+        if result.is_err() {
+            self.pc = pc;
+        }
+        result
     }
 
-    fn process_opcode(&mut self, opcode: u16) -> (u16, &str) {
+    /// Opcode at `address`, `None` past the end of memory
+    pub fn opcode_at(&self, address: u16) -> Option<u16> {
+        (address as usize + 1 < self.memory.len()).then(|| read_opcode(&self.memory, address))
+    }
 
-        let x = ((opcode & 0x0F00) >> 8) as usize;
-        let y = ((opcode & 0x00F0) >> 4) as usize;
-        let vx = self.v[x];
-        let vy = self.v[y];
-        let n = opcode & 0x000F;
-        let kk = (opcode & 0x00FF) as u8;
-        let nnn = opcode & 0x0FFF;
+    /// Appends the CPU and its components to a save state
+    pub(crate) fn save_state(&self, state: &mut StateWriter) {
+        self.platform.save_state(state);
+        self.quirks.save_state(state);
+        state.u16(self.i);
+        state.u16(self.pc);
+        state.bytes(&self.memory);
+        state.array(&self.v);
+        self.stack.save_state(state);
+        state.u8(self.dt);
+        state.u8(self.st);
+        self.audio.save_state(state);
+        self.display.save_state(state);
+        self.keypad.save_state(state);
+        self.random.save_state(state);
+        state.array(&self.rpl);
+        state.bool(self.exited);
+    }
 
-        let op_1 = (opcode & 0xF000) >> 12;
-        let op_2 = (opcode & 0x0F00) >> 8;
-        let op_3 = (opcode & 0x00F0) >> 4;
-        let op_4 = opcode & 0x000F;
+    pub(crate) fn load_state(&mut self, state: &mut StateReader) -> Result<(), Chip8Error> {
+        self.platform.load_state(state)?;
+        self.quirks.load_state(state)?;
+        self.i = state.u16()?;
+        self.pc = state.u16()?;
+        self.memory = state.bytes()?;
+        if self.memory.len() != self.platform.memory_size() {
+            return Err(invalid("the memory size does not match the platform"));
+        }
+        self.v = state.array()?;
+        self.stack.load_state(state)?;
+        self.dt = state.u8()?;
+        self.st = state.u8()?;
+        self.audio.load_state(state)?;
+        self.display.load_state(state)?;
+        self.keypad.load_state(state)?;
+        self.random.load_state(state)?;
+        self.rpl = state.array()?;
+        self.exited = state.bool()?;
+        Ok(())
+    }
 
-        let mut operation_type = "Unknown";
+    /// Decreases both timers, meant to be called at 60 Hz
+    pub fn tick_timers(&mut self) {
+        if self.st > 0 { self.st -= 1 };
+        if self.dt > 0 { self.dt -= 1 };
+    }
 
-        match (op_1, op_2, op_3, op_4) {
-            (0, 0, 0xE, 0) => {
-                self.display.cls();
-                operation_type = "Display";
-            }
-            (0, 0, 0xE, 0xE) => {
-                self.pc = self.stack.pop().unwrap_or(512);
-                operation_type = "Flow";
-            }
-            (0x1, _, _, _) => {
-                self.pc = nnn;
-                operation_type = "Flow";
-            }
-            (0x2, _, _, _) => {
-                self.stack.push(self.pc);
+    fn process_opcode(&mut self, opcode: u16) -> Result<(u16, &'static str), Chip8Error> {
+        // address of this instruction, for the errors
+        let pc = self.pc.wrapping_sub(2);
+
+        let Some(instruction) = Instruction::decode(opcode, self.platform) else {
+            return Err(Chip8Error::UnknownOpcode { pc, opcode });
+        };
+
+        match instruction {
+            Instruction::Cls => self.display.cls(),
+            Instruction::Ret => {
+                self.pc = match self.stack.pop() {
+                    Some(address) => address,
+                    None if self.quirks.stack_policy == StackPolicy::Wrap => self.stack.pop_wrapping(),
+                    None => return Err(Chip8Error::StackUnderflow { pc, opcode }),
+                };
+            }
+            Instruction::ScrollDown(n) => self.display.scroll_down(n as usize),
+            Instruction::ScrollUp(n) => self.display.scroll_up(n as usize),
+            Instruction::ScrollRight => self.display.scroll_right(4),
+            Instruction::ScrollLeft => self.display.scroll_left(4),
+            Instruction::Exit => {
+                // stay on the exit instruction
+                self.exited = true;
+                self.pc = self.pc.wrapping_sub(2);
+            }
+            Instruction::Lores => {
+                self.display.set_hires(false);
+                self.display.select_planes(1);
+            }
+            Instruction::Hires => self.display.set_hires(true),
+            Instruction::Jump(nnn) => self.pc = nnn,
+            Instruction::Call(nnn) => {
+                if !self.stack.push(self.pc) {
+                    if self.quirks.stack_policy != StackPolicy::Wrap {
+                        return Err(Chip8Error::StackOverflow { pc, opcode });
+                    }
+                    self.stack.push_wrapping(self.pc);
+                }
                 self.pc = nnn;
-                operation_type = "Flow";
             }
-            (0x3, _, _, _) => {
-                if vx == kk {
-                    self.pc += 2;
+            Instruction::SkipEqByte { x, kk } => {
+                if self.v[x] == kk {
+                    self.skip();
                 }
-                operation_type = "Cond";
             }
-            (0x4, _, _, _) => {
-                if vx != kk {
-                    self.pc += 2;
+            Instruction::SkipNeByte { x, kk } => {
+                if self.v[x] != kk {
+                    self.skip();
                 }
-                operation_type = "Cond";
             }
-            (0x5, _, _, 0) => {
-                if vx == vy {
-                    self.pc += 2;
+            Instruction::SkipEq { x, y } => {
+                if self.v[x] == self.v[y] {
+                    self.skip();
                 }
-                operation_type = "Cond";
             }
-            (0x6, _, _, _) => {
-                self.v[x] = kk;
-                operation_type = "Const";
-            }
-            (0x7, _, _, _) => {
-                self.v[x] = ((vx as u16 + kk as u16) & 0xFF) as u8;
-                operation_type = "Const";
+            Instruction::SaveRange { x, y } => {
+                let range = self.memory_range(self.i as usize, x.abs_diff(y) + 1, pc, opcode)?;
+                for (offset, register) in register_range(x, y).enumerate() {
+                    self.memory[range.start + offset] = self.v[register];
+                }
             }
-            (0x8, _, _, 0) => {
-                self.v[x] = vy;
-                operation_type = "Assig";
+            Instruction::LoadRange { x, y } => {
+                let range = self.memory_range(self.i as usize, x.abs_diff(y) + 1, pc, opcode)?;
+                for (offset, register) in register_range(x, y).enumerate() {
+                    self.v[register] = self.memory[range.start + offset];
+                }
             }
-            (0x8, _, _, 1) => {
-                self.v[x] |= vy;
-                operation_type = "BitOp";
+            Instruction::LoadByte { x, kk } => self.v[x] = kk,
+            Instruction::AddByte { x, kk } => self.v[x] = self.v[x].wrapping_add(kk),
+            Instruction::Load { x, y } => self.v[x] = self.v[y],
+            Instruction::Or { x, y } => {
+                self.v[x] |= self.v[y];
+                if self.quirks.vf_reset { self.v[0xF] = 0; }
             }
-            (0x8, _, _, 2) => {
-                self.v[x] &= vy;
-                operation_type = "BitOp";
+            Instruction::And { x, y } => {
+                self.v[x] &= self.v[y];
+                if self.quirks.vf_reset { self.v[0xF] = 0; }
             }
-            (0x8, _, _, 3) => {
-                self.v[x] ^= vy;
-                operation_type = "BitOp";
+            Instruction::Xor { x, y } => {
+                self.v[x] ^= self.v[y];
+                if self.quirks.vf_reset { self.v[0xF] = 0; }
             }
-            (0x8, _, _, 4) => {
+            Instruction::Add { x, y } => {
+                let (vx, vy) = (self.v[x], self.v[y]);
                 if vx as u16 + vy as u16 > 0xFF {
                     self.v[x] = (vx as u16 + vy as u16 - 0x100) as u8;
                     self.v[0xF] = 1;
@@ -177,9 +270,9 @@ impl Cpu {
                     self.v[x] += vy;
                     self.v[0xF] = 0;
                 }
-                operation_type = "Math";
             }
-            (0x8, _, _, 5) => {
+            Instruction::Sub { x, y } => {
+                let (vx, vy) = (self.v[x], self.v[y]);
                 if vx < vy {
                     self.v[x] = (256 - (vy - vx) as u16) as u8;
                     self.v[0xF] = 0;
@@ -187,186 +280,215 @@ impl Cpu {
                     self.v[x] -= vy;
                     self.v[0xF] = 1;
                 }
-                operation_type = "Math";
             }
-            (0x8, _, _, 6) => {
+            Instruction::Shr { x, y } => {
+                let vx = if self.quirks.shift { self.v[x] } else { self.v[y] };
+                self.v[x] = vx >> 1;
                 self.v[0xF] = vx & 0x1;
-                self.v[x] >>= 1;
-                operation_type = "BitOp";
             }
-            (0x8, _, _, 7) => {
-                if vy < vx {
-                    self.v[0xF] = 0;
-                    self.v[x] = (vx - vy) ^ 0xFF;
-                } else {
-                    self.v[x] = vy - vx;
-                    self.v[0xF] = 1;
-                }
-                operation_type = "Math";
+            Instruction::SubN { x, y } => {
+                let (vx, vy) = (self.v[x], self.v[y]);
+                // the flag goes last, it wins when X is F
+                self.v[x] = vy.wrapping_sub(vx);
+                self.v[0xF] = (vy >= vx) as u8;
             }
-            (0x8, _, _, 0xE) => {
-                self.v[0xF] = vx & 0x80;
-                self.v[x] <<= 1;
-                operation_type = "BitOp";
+            Instruction::Shl { x, y } => {
+                let vx = if self.quirks.shift { self.v[x] } else { self.v[y] };
+                self.v[x] = vx << 1;
+                self.v[0xF] = vx >> 7;
             }
-            (0x9, _, _, 0) => {
-                if vx != vy {
-                    self.pc += 2;
+            Instruction::SkipNe { x, y } => {
+                if self.v[x] != self.v[y] {
+                    self.skip();
                 }
-                operation_type = "Cond";
-            }
-            (0xA, _, _, _) => {
-                self.i = nnn;
-                operation_type = "MEM";
             }
-            (0xB, _, _, _) => {
-                self.pc = nnn + self.v[0] as u16;
-                operation_type = "Flow";
+            Instruction::LoadI(nnn) => self.i = nnn,
+            Instruction::JumpOffset { x, nnn } => {
+                let offset = if self.quirks.jump { self.v[x] } else { self.v[0] };
+                self.pc = nnn + offset as u16;
             }
-            (0xC, _, _, _) => {
-                self.v[x] = self.random.change() & kk;
-                operation_type = "Rand";
+            Instruction::Random { x, kk } => self.v[x] = self.random.change() & kk,
+            // the COSMAC VIP draws nothing for DXY0
+            Instruction::Draw { x, y, n: 0 } if self.platform != Platform::Chip8 => {
+                self.memory_range(self.i as usize, self.sprite_size(16, 16), pc, opcode)?;
+                self.v[0xF] = self.screen_sprite(self.v[x], self.v[y], 16, 16);
             }
-            (0xD, _, _, _) => {
-                self.v[0xF] = self.screen_sprite(vx, vy, n);
-
-                if !cfg!(feature = "window") {
-                    self.display.print_to_console();
-                }
-                operation_type = "Display";
+            Instruction::Draw { x, y, n } => {
+                self.memory_range(self.i as usize, self.sprite_size(n as u16, 8), pc, opcode)?;
+                self.v[0xF] = self.screen_sprite(self.v[x], self.v[y], n as u16, 8);
             }
-            (0xE, _, 0x9, 0xE) => {
-                if self.keypad.get_key_pressed().unwrap() == vx {
-                    self.pc += 2;
+            Instruction::SkipKey(x) => {
+                if self.keypad.is_pressed(self.v[x] & 0xF) {
+                    self.skip();
                 }
-                operation_type = "KeyOp";
             }
-            (0xE, _, 0xA, 0x1) => {
-                if self.keypad.get_key_pressed().unwrap() != vx {
-                    self.pc += 2;
+            Instruction::SkipNotKey(x) => {
+                if !self.keypad.is_pressed(self.v[x] & 0xF) {
+                    self.skip();
                 }
-                operation_type = "KeyOp";
             }
-            (0xF, _, 0, 0x7) => {
-                self.v[x] = self.dt;
-                operation_type = "Timer";
-            }
-            (0xF, _, 0, 0xA) => {
-                self.v[x] = self.keypad.await_key_press();
-                operation_type = "KeyOp";
-            }
-            (0xF, _, 0x1, 0x5) => {
-                self.dt = vx;
-                operation_type = "Timer";
-            }
-            (0xF, _, 0x1, 0x8) => {
-                self.st = vx;
-                operation_type = "Sound";
-            }
-            (0xF, _, 0x1, 0xE) => {
-                self.i += vx as u16;
-                operation_type = "MEM";
-            }
-            (0xF, _, 0x2, 0x9) => {
-                self.i = (vx as u16 & 0xF) * 5;
-                operation_type = "MEM";
-            }
-            (0xF, _, 0x3, 0x3) => {
-                self.memory[self.i as usize] = vx / 100;
-                self.memory[(self.i + 1) as usize] = (vx / 10) % 10;
-                self.memory[(self.i + 2) as usize] = vx % 10;
-                operation_type = "BCD";
-            }
-            (0xF, _, 0x5, 0x5) => {
-                for offset in 0..=x {
-                    self.memory[self.i as usize + offset] = self.v[offset];
+            Instruction::LoadLongI => {
+                self.memory_range(self.pc as usize, 2, pc, opcode)?;
+                self.i = read_opcode(&self.memory, self.pc);
+                self.pc = self.pc.wrapping_add(2);
+            }
+            Instruction::Plane(planes) => self.display.select_planes(planes),
+            Instruction::Audio => {
+                let range = self.memory_range(self.i as usize, 16, pc, opcode)?;
+                let mut pattern = [0; 16];
+                pattern.copy_from_slice(&self.memory[range]);
+                self.audio.pattern = Some(pattern);
+            }
+            Instruction::GetDelay(x) => self.v[x] = self.dt,
+            Instruction::WaitKey(x) => {
+                // repeat the instruction until a key is pressed and released
+                match self.keypad.await_key() {
+                    Some(key) => self.v[x] = key,
+                    None => self.pc = self.pc.wrapping_sub(2),
                 }
-                operation_type = "MEM";
             }
-            (0xF, _, 0x6, 0x5) => {
-                for offset in 0..=x {
-                    self.v[offset] = self.memory[self.i as usize + offset];
-                }
-                operation_type = "MEM";
-            }
-            (_, _, _, _) => ()
+            Instruction::SetDelay(x) => self.dt = self.v[x],
+            Instruction::SetSound(x) => self.st = self.v[x],
+            Instruction::AddI(x) => self.i = self.i.wrapping_add(self.v[x] as u16),
+            Instruction::Font(x) => self.i = (self.v[x] as u16 & 0xF) * 5,
+            Instruction::BigFont(x) => self.i = BIG_FONT_ADDRESS as u16 + (self.v[x] as u16 & 0xF) * 10,
+            Instruction::Pitch(x) => self.audio.pitch = self.v[x],
+            Instruction::Bcd(x) => {
+                let vx = self.v[x];
+                let range = self.memory_range(self.i as usize, 3, pc, opcode)?;
+                self.memory[range].copy_from_slice(&[vx / 100, (vx / 10) % 10, vx % 10]);
+            }
+            Instruction::Store(x) => {
+                let range = self.memory_range(self.i as usize, x + 1, pc, opcode)?;
+                self.memory[range].copy_from_slice(&self.v[..=x]);
+                self.increment_i(x);
+            }
+            Instruction::Read(x) => {
+                let range = self.memory_range(self.i as usize, x + 1, pc, opcode)?;
+                self.v[..=x].copy_from_slice(&self.memory[range]);
+                self.increment_i(x);
+            }
+            Instruction::SaveFlags(x) => self.rpl[..=x].copy_from_slice(&self.v[..=x]),
+            Instruction::LoadFlags(x) => self.v[..=x].copy_from_slice(&self.rpl[..=x]),
         }
-        (opcode, operation_type)
+        Ok((opcode, instruction.kind()))
     }
 
-    fn display_debug(cpu: Cpu, operation_code: u16, operation_type: &str) {
-        let opcode_styled: StyledContent<String>;
-        let optype_styled: StyledContent<String>;
-        let mut output_color = crossterm::style::Color::Green;
-
-        let cpu_styled: StyledContent<String> = crossterm::style::style(format!("\ti={:?},\n\tpc={:?},\n\tv={:?},\n\tstack={:?},\n\tdt={:?},\n\tst={:?}",
-        cpu.i, cpu.pc, cpu.v, cpu.stack, cpu.dt, cpu.st)).with(crossterm::style::Color::DarkYellow);
-
-        if operation_type == "Unknown" {
-            output_color = crossterm::style::Color::Red;
-        }                
-        opcode_styled = crossterm::style::style(format!("{:04X}", operation_code)).with(output_color);
-        optype_styled = crossterm::style::style(operation_type.to_string()).with(output_color);
-        
-        let mut stdout = std::io::stdout();
-        
-        crossterm::queue!(stdout,
-            MoveTo(0, 60),
-            PrintStyledContent(opcode_styled),
-            PrintStyledContent(optype_styled),
-            PrintStyledContent(cpu_styled),
-        ).unwrap();
-        stdout.flush().unwrap();
-        
-        // Await key press
-        let _ = std::io::stdin().read_line(&mut String::new());
+    /// `len` bytes of memory from `address`, or the error the instruction at `pc` raises
+    fn memory_range(&self, address: usize, len: usize, pc: u16, opcode: u16) -> Result<Range<usize>, Chip8Error> {
+        if address + len > self.memory.len() {
+            return Err(Chip8Error::MemoryOutOfBounds { pc, opcode, address: address.max(self.memory.len()) });
+        }
+        Ok(address..address + len)
     }
 
-    fn screen_sprite(&mut self, register_x: u8, register_y: u8, first_char: u16) -> u8 {
-        let mut overflow_flag: u8 = 0;
+    /// Bytes read by a sprite draw on the selected planes
+    fn sprite_size(&self, rows: u16, sprite_width: usize) -> usize {
+        self.display.selected_planes().count_ones() as usize * rows as usize * sprite_width / 8
+    }
+
+    /// Skips the next instruction, including the second half of XO-CHIP's F000 NNNN
+    fn skip(&mut self) {
+        let in_memory = (self.pc as usize + 1) < self.memory.len();
+        if self.platform == Platform::XoChip && in_memory && read_opcode(&self.memory, self.pc) == 0xF000 {
+            self.pc = self.pc.wrapping_add(2);
+        }
+        self.pc = self.pc.wrapping_add(2);
+    }
+
+    fn increment_i(&mut self, x: usize) {
+        match self.quirks.memory_increment {
+            MemoryIncrement::None => (),
+            MemoryIncrement::X => self.i = self.i.wrapping_add(x as u16),
+            MemoryIncrement::XPlusOne => self.i = self.i.wrapping_add(x as u16 + 1),
+        }
+    }
 
-        let mut xcoord = register_x % WIDTH as u8;
-        let mut ycoord = register_y % HEIGHT as u8;
+    /// Draws a `sprite_width` (8 or 16) pixels wide sprite from I, returns VF
+    ///
+    /// With both XO-CHIP planes selected the second plane's sprite follows the first one.
+    fn screen_sprite(&mut self, register_x: u8, register_y: u8, rows: u16, sprite_width: usize) -> u8 {
+        let mut overflow_flag: u8 = 0;
+        let width = self.display.width();
+        let height = self.display.height();
+        let row_bytes = sprite_width / 8;
+        let mut address = self.i as usize;
+
+        // the starting position always wraps, only the sprite itself may be clipped
+        let start_x = register_x as usize % width;
+        let start_y = register_y as usize % height;
+
+        for plane in 0..2 {
+            if self.display.selected_planes() & (1 << plane) == 0 {
+                continue;
+            }
+
+            for voffset in 0..rows as usize {
+                let row_address = address + voffset * row_bytes;
+                let mut ycoord = start_y + voffset;
+                if ycoord >= height {
+                    if self.quirks.clipping { break; }
+                    ycoord %= height;
+                }
 
-        for voffset in 0..first_char {
-            let sprite: u8 = self.memory[(self.i + voffset) as usize];
-            for hoffset in 0..8 {
-                if (sprite & (0b10000000 >> hoffset)) != 0 {
-                    overflow_flag |= self.display.draw(xcoord, ycoord) as u8;
+                let sprite: u16 = if row_bytes == 2 {
+                    (self.memory[row_address] as u16) << 8 | self.memory[row_address + 1] as u16
+                } else {
+                    (self.memory[row_address] as u16) << 8
+                };
+
+                for hoffset in 0..sprite_width {
+                    let mut xcoord = start_x + hoffset;
+                    if xcoord >= width {
+                        if self.quirks.clipping { break; }
+                        xcoord %= width;
+                    }
+
+                    if (sprite & (0x8000 >> hoffset)) != 0 {
+                        overflow_flag |= self.display.draw(plane, xcoord, ycoord) as u8;
+                    }
                 }
-                xcoord += 1;
-                if xcoord >= WIDTH as u8 { break; }
             }
-            xcoord = register_x % WIDTH as u8;
-            
-            ycoord += 1;
-            if ycoord >= HEIGHT as u8 { break; }
+            address += rows as usize * row_bytes;
         }
         overflow_flag
     }
 }
 
+/// Registers X to Y, walking backwards when X > Y (XO-CHIP 5XY2/5XY3)
+fn register_range(x: usize, y: usize) -> Box<dyn Iterator<Item = usize>> {
+    if x <= y {
+        Box::new(x..=y)
+    } else {
+        Box::new((y..=x).rev())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::{Cpu, read_opcode};
+    use crate::error::Chip8Error;
+    use crate::components::platform::Platform;
+    use crate::components::quirks::{Quirks, StackPolicy};
     
     #[test]
     fn it_resets() {
         let mut cpu = Cpu::new();
         cpu.i = 2001;
         cpu.pc = 2002;
-        cpu.memory = [4; 4096];
+        cpu.memory = vec![4; 4096];
         cpu.v = [1; 16];
-        cpu.stack = vec![1, 2, 3];
+        cpu.stack.push(1);
+        cpu.stack.push(2);
         cpu.dt = 120;
         cpu.st = 120;
         cpu.reset();
 
         assert_eq!(cpu.i, 0, "i does not reset");
         assert_eq!(cpu.pc, 512, "pc does not reset");
-        assert_eq!(cpu.memory[80..], [0; 4096][80..], "memory does not reset");
+        assert_eq!(cpu.memory[240..], [0; 4096][240..], "memory does not reset");
         assert_eq!(cpu.v, [0; 16], "register does not reset");
-        assert_eq!(cpu.stack, vec![], "stack does not reset");
+        assert!(cpu.stack.is_empty(), "stack does not reset");
         assert_eq!(cpu.dt, 0, "delay timer does not reset");
         assert_eq!(cpu.st, 0, "sound timer does not reset");
     }
@@ -375,10 +497,11 @@ mod tests {
     fn it_increments() {
         let mut cpu = Cpu::new();
         cpu.reset();
-        cpu.execute_cycle();
-        cpu.execute_cycle();
-        cpu.execute_cycle();
-        cpu.execute_cycle();
+        cpu.load_program(vec![0x60, 0x00, 0x61, 0x00, 0x62, 0x00, 0x63, 0x00]).unwrap();
+        cpu.execute_cycle().unwrap();
+        cpu.execute_cycle().unwrap();
+        cpu.execute_cycle().unwrap();
+        cpu.execute_cycle().unwrap();
 
         assert_eq!(cpu.pc, 512 + 8, "pc does not increment");
     }
@@ -388,15 +511,15 @@ mod tests {
         let mut cpu = Cpu::new();
         cpu.reset();
 
-        let mut opcode = read_opcode(cpu.memory, 0);
+        let mut opcode = read_opcode(&cpu.memory, 0);
         assert_eq!(opcode, 0xF090, "wrong opcode read");
 
-        opcode = read_opcode(cpu.memory, 81);
+        opcode = read_opcode(&cpu.memory, 241);
         assert_eq!(opcode, 0x0000, "wrong opcode read");
 
         cpu.memory[202] = 0xFA;
         cpu.memory[203] = 0x02;
-        opcode = read_opcode(cpu.memory, 202);
+        opcode = read_opcode(&cpu.memory, 202);
         assert_eq!(opcode, 0xFA02, "wrong opcode read");
     }
 
@@ -405,11 +528,274 @@ mod tests {
         let mut cpu = Cpu::new();
         cpu.reset();
 
-        cpu.load_program(vec![0x12, 0x24, 0xFD, 0x0A]);
+        cpu.load_program(vec![0x12, 0x24, 0xFD, 0x0A]).unwrap();
 
         assert_eq!(cpu.memory[0x200], 0x12, "program not loaded");
         assert_eq!(cpu.memory[0x200 + 1], 0x24, "program not loaded");
         assert_eq!(cpu.memory[0x200 + 2], 0xFD, "program not loaded");
         assert_eq!(cpu.memory[0x200 + 3], 0x0A, "program not loaded");
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn it_reports_errors() {
+        let mut cpu = Cpu::new();
+        cpu.reset();
+
+        assert!(matches!(cpu.load_program(vec![0; 3585]), Err(Chip8Error::RomTooLarge { size: 3585, max: 3584 })), "oversized ROM loaded");
+
+        // 00EE, 2200, then an unknown opcode
+        cpu.load_program(vec![0x00, 0xEE, 0x22, 0x02, 0x01, 0x23]).unwrap();
+        assert!(matches!(cpu.execute_cycle(), Err(Chip8Error::StackUnderflow { pc: 0x200, opcode: 0x00EE })), "empty stack popped");
+        assert_eq!(cpu.pc, 0x200, "pc left the failed instruction");
+
+        cpu.pc = 0x202;
+        for _ in 0..12 {
+            cpu.execute_cycle().unwrap();
+        }
+        assert!(matches!(cpu.execute_cycle(), Err(Chip8Error::StackOverflow { pc: 0x202, .. })), "VIP stack deeper than 12");
+
+        cpu.pc = 0x204;
+        assert!(matches!(cpu.execute_cycle(), Err(Chip8Error::UnknownOpcode { pc: 0x204, opcode: 0x0123 })), "unknown opcode executed");
+
+        cpu.i = 0xFFE;
+        assert!(matches!(cpu.process_opcode(0xF033), Err(Chip8Error::MemoryOutOfBounds { address: 0x1000, .. })), "BCD written past memory");
+
+        cpu.pc = 0xFFF;
+        assert!(matches!(cpu.execute_cycle(), Err(Chip8Error::PcOutOfBounds { pc: 0xFFF })), "opcode read past memory");
+    }
+
+    #[test]
+    fn it_wraps_the_stack() {
+        let mut cpu = Cpu::new();
+        cpu.quirks.stack_depth = 2;
+        cpu.quirks.stack_policy = StackPolicy::Wrap;
+        cpu.reset();
+
+        // 2200 calls itself forever, 00EE at 0x300
+        cpu.load_program(vec![0x22, 0x00]).unwrap();
+        for _ in 0..3 {
+            cpu.execute_cycle().unwrap();
+        }
+        assert_eq!(cpu.stack.len(), 2, "stack grew past its depth");
+
+        cpu.pc = 0x300;
+        cpu.memory[0x300] = 0x00;
+        cpu.memory[0x301] = 0xEE;
+        for _ in 0..3 {
+            cpu.pc = 0x300;
+            cpu.execute_cycle().unwrap();
+        }
+        assert_eq!(cpu.pc, 0x202, "underflow does not wrap");
+    }
+
+    #[test]
+    fn it_follows_shift_quirk() {
+        let mut cpu = Cpu::new();
+        cpu.reset();
+        cpu.v[1] = 0b0000_0011;
+        cpu.v[2] = 0b0000_0100;
+
+        cpu.quirks = Quirks::chip8();
+        cpu.process_opcode(0x8126).unwrap();
+        assert_eq!(cpu.v[1], 0b0000_0010, "8XY6 does not shift VY");
+        assert_eq!(cpu.v[0xF], 0, "8XY6 sets wrong VF");
+
+        cpu.v[1] = 0b0000_0011;
+        cpu.quirks = Quirks::schip();
+        cpu.process_opcode(0x8126).unwrap();
+        assert_eq!(cpu.v[1], 0b0000_0001, "8XY6 does not shift VX in place");
+        assert_eq!(cpu.v[0xF], 1, "8XY6 sets wrong VF");
+    }
+
+    #[test]
+    fn it_sets_flags_after_the_result() {
+        let mut cpu = Cpu::new();
+        cpu.reset();
+        cpu.quirks = Quirks::schip();
+
+        cpu.v[1] = 0b1000_0001;
+        cpu.process_opcode(0x811E).unwrap();
+        assert_eq!((cpu.v[1], cpu.v[0xF]), (0b0000_0010, 1), "8XYE does not set VF to the shifted out bit");
+
+        cpu.v[1] = 0x30;
+        cpu.v[2] = 0x10;
+        cpu.process_opcode(0x8127).unwrap();
+        assert_eq!((cpu.v[1], cpu.v[0xF]), (0xE0, 0), "8XY7 borrows wrong");
+        cpu.v[1] = 0x10;
+        cpu.v[2] = 0x30;
+        cpu.process_opcode(0x8127).unwrap();
+        assert_eq!((cpu.v[1], cpu.v[0xF]), (0x20, 1), "8XY7 subtracts wrong");
+
+        cpu.v[0xF] = 0x30;
+        cpu.process_opcode(0x8F27).unwrap();
+        assert_eq!(cpu.v[0xF], 1, "8XY7 result overwrites VF");
+        cpu.v[0xF] = 0x80;
+        cpu.process_opcode(0x8FFE).unwrap();
+        assert_eq!(cpu.v[0xF], 1, "8XYE result overwrites VF");
+    }
+
+    #[test]
+    fn it_follows_memory_quirk() {
+        let mut cpu = Cpu::new();
+        cpu.reset();
+        cpu.i = 0x300;
+
+        cpu.quirks = Quirks::chip8();
+        cpu.process_opcode(0xF255).unwrap();
+        assert_eq!(cpu.i, 0x303, "FX55 does not increment I");
+
+        cpu.quirks = Quirks::chip48();
+        cpu.process_opcode(0xF265).unwrap();
+        assert_eq!(cpu.i, 0x305, "FX65 does not increment I by X");
+
+        cpu.quirks = Quirks::schip();
+        cpu.process_opcode(0xF255).unwrap();
+        assert_eq!(cpu.i, 0x305, "FX55 changes I");
+    }
+
+    #[test]
+    fn it_follows_jump_and_vf_reset_quirks() {
+        let mut cpu = Cpu::new();
+        cpu.reset();
+        cpu.v[0] = 0x10;
+        cpu.v[3] = 0x20;
+
+        cpu.quirks = Quirks::chip8();
+        cpu.process_opcode(0xB300).unwrap();
+        assert_eq!(cpu.pc, 0x310, "BNNN does not add V0");
+        cpu.v[0xF] = 1;
+        cpu.process_opcode(0x8031).unwrap();
+        assert_eq!(cpu.v[0xF], 0, "8XY1 does not reset VF");
+
+        cpu.quirks = Quirks::schip();
+        cpu.process_opcode(0xB300).unwrap();
+        assert_eq!(cpu.pc, 0x320, "BXNN does not add VX");
+        cpu.v[0xF] = 1;
+        cpu.process_opcode(0x8031).unwrap();
+        assert_eq!(cpu.v[0xF], 1, "8XY1 resets VF");
+    }
+
+    #[test]
+    fn it_clips_or_wraps_sprites() {
+        let mut cpu = Cpu::new();
+        cpu.reset();
+        // "0" glyph at the bottom-right corner
+        cpu.i = 0;
+        cpu.v[0] = 62;
+        cpu.v[1] = 30;
+
+        cpu.quirks.clipping = true;
+        cpu.process_opcode(0xD015).unwrap();
+        assert!(!cpu.display.memory[1], "sprite is not clipped");
+
+        cpu.display.cls();
+        cpu.quirks.clipping = false;
+        cpu.process_opcode(0xD015).unwrap();
+        assert!(cpu.display.memory[1], "sprite does not wrap");
+    }
+
+    #[test]
+    fn it_switches_resolution() {
+        let mut cpu = Cpu::new();
+        cpu.reset();
+        // a 16x16 sprite on SUPER-CHIP, nothing on the COSMAC VIP
+        cpu.v[0] = 0;
+        cpu.process_opcode(0xD000).unwrap();
+        assert!(!cpu.display.memory[0], "DXY0 draws on CHIP-8");
+
+        cpu.platform = Platform::SuperChip;
+        cpu.reset();
+
+        cpu.process_opcode(0x00FF).unwrap();
+        assert_eq!((cpu.display.width(), cpu.display.height()), (128, 64), "00FF does not enable hires");
+
+        // big "8" at the bottom-right corner
+        cpu.v[0] = 8;
+        cpu.v[1] = 120;
+        cpu.v[2] = 54;
+        cpu.process_opcode(0xF030).unwrap();
+        cpu.process_opcode(0xD12A).unwrap();
+        assert!(cpu.display.memory[120 + 54 * 128], "big font not drawn");
+        assert!(cpu.display.memory[127 + 63 * 128], "big font not drawn");
+
+        cpu.process_opcode(0x00FC).unwrap();
+        assert!(!cpu.display.memory[127 + 54 * 128], "00FC does not scroll left");
+        assert!(cpu.display.memory[116 + 54 * 128], "00FC does not scroll left");
+
+        cpu.process_opcode(0x00FE).unwrap();
+        assert_eq!(cpu.display.memory.len(), 64 * 32, "00FE does not disable hires");
+    }
+
+    #[test]
+    fn it_exits() {
+        let mut cpu = Cpu::new();
+        cpu.platform = Platform::SuperChip;
+        cpu.reset();
+        cpu.load_program(vec![0x00, 0xFD]).unwrap();
+
+        cpu.execute_cycle().unwrap();
+        cpu.execute_cycle().unwrap();
+
+        assert!(cpu.exited, "00FD does not exit");
+        assert_eq!(cpu.pc, 512, "pc moves after exit");
+    }
+
+    #[test]
+    fn it_stays_on_the_last_instruction_of_memory() {
+        // 00FD and F00A without a key held, at the end of XO-CHIP memory where pc wraps to 0
+        for opcode in [0x00FD, 0xF00A] {
+            let mut cpu = Cpu::new();
+            cpu.platform = Platform::XoChip;
+            cpu.reset();
+            cpu.memory[0xFFFE..].copy_from_slice(&u16::to_be_bytes(opcode));
+            cpu.pc = 0xFFFE;
+
+            cpu.execute_cycle().unwrap();
+            assert_eq!(cpu.pc, 0xFFFE, "pc does not stay on {:04X}", opcode);
+        }
+    }
+
+    #[test]
+    fn it_runs_xo_chip_extensions() {
+        let mut cpu = Cpu::new();
+        cpu.platform = Platform::XoChip;
+        cpu.reset();
+        assert_eq!(cpu.memory.len(), 0x10000, "XO-CHIP memory is not 64 KiB");
+
+        // skip over the 4-byte long I load
+        cpu.load_program(vec![0x30, 0x00, 0xF0, 0x00, 0xE0, 0x00, 0xF0, 0x00, 0xE0, 0x00]).unwrap();
+        cpu.execute_cycle().unwrap();
+        assert_eq!(cpu.pc, 0x206, "3XNN does not skip F000 NNNN");
+        cpu.execute_cycle().unwrap();
+        assert_eq!(cpu.i, 0xE000, "F000 NNNN does not load I");
+        assert_eq!(cpu.pc, 0x20A, "F000 NNNN does not skip its operand");
+
+        cpu.v[1] = 1;
+        cpu.v[2] = 2;
+        cpu.v[3] = 3;
+        cpu.process_opcode(0x5312).unwrap();
+        assert_eq!(cpu.memory[0xE000..0xE003], [3, 2, 1], "5XY2 does not save backwards");
+        assert_eq!(cpu.i, 0xE000, "5XY2 changes I");
+        cpu.process_opcode(0x5133).unwrap();
+        assert_eq!(cpu.v[1..4], [3, 2, 1], "5XY3 does not load");
+    }
+
+    #[test]
+    fn it_draws_on_selected_planes() {
+        let mut cpu = Cpu::new();
+        cpu.platform = Platform::XoChip;
+        cpu.reset();
+        cpu.memory[0x300] = 0x80;
+        cpu.memory[0x301] = 0x40;
+        cpu.i = 0x300;
+
+        cpu.process_opcode(0xF301).unwrap();
+        cpu.process_opcode(0xD001).unwrap();
+        assert_eq!(cpu.display.pixel(0), 0b01, "first plane sprite not drawn");
+        assert_eq!(cpu.display.pixel(1), 0b10, "second plane sprite not drawn");
+
+        cpu.process_opcode(0xF201).unwrap();
+        cpu.process_opcode(0x00E0).unwrap();
+        assert_eq!((cpu.display.pixel(0), cpu.display.pixel(1)), (0b01, 0), "00E0 clears unselected plane");
+    }
+}
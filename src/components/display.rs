@@ -1,15 +1,34 @@
-use std::{env, io::Write};
+use std::env;
 
-use crossterm::{style::Print, cursor::MoveTo };
-use minifb::{Window, WindowOptions};
+use crate::error::Chip8Error;
+use crate::state::{invalid, StateReader, StateWriter};
 
+/// Low resolution (CHIP-8) width
 pub const WIDTH: usize = 64;
+/// Low resolution (CHIP-8) height
 pub const HEIGHT: usize = 32;
+/// High resolution (SUPER-CHIP) width
+pub const HIRES_WIDTH: usize = 128;
+/// High resolution (SUPER-CHIP) height
+pub const HIRES_HEIGHT: usize = 64;
 pub const ON: u32 = 0xFF_FF_FF_FF;
 pub const OFF: u32 = 0x0;
+/// XO-CHIP pixel set only on the second plane
+pub const PLANE2: u32 = 0x00_55_55_55;
+/// XO-CHIP pixel set on both planes
+pub const BLEND: u32 = 0x00_AA_AA_AA;
 
 pub struct Display {
-    pub memory: [bool; WIDTH * HEIGHT],
+    /// first plane pixels row by row, `width() * height()` of them
+    pub memory: Vec<bool>,
+    /// second XO-CHIP plane, laid out like `memory`
+    pub plane2: Vec<bool>,
+    /// 128x64 SUPER-CHIP mode
+    hires: bool,
+    /// planes affected by drawing, clearing and scrolling - bit 0 is `memory`, bit 1 is `plane2`
+    selected_planes: u8,
+    /// colours used by `get_screen_buffer`
+    pub palette: Palette,
 }
 
 impl Default for Display {
@@ -21,89 +40,199 @@ impl Default for Display {
 impl Display {
     pub fn new() -> Self {
         Display {
-            memory : [false; WIDTH * HEIGHT],
+            memory : vec![false; WIDTH * HEIGHT],
+            plane2 : vec![false; WIDTH * HEIGHT],
+            hires : false,
+            selected_planes : 1,
+            palette : Palette::default(),
         }
     }
 
+    pub fn width(&self) -> usize {
+        if self.hires { HIRES_WIDTH } else { WIDTH }
+    }
+
+    pub fn height(&self) -> usize {
+        if self.hires { HIRES_HEIGHT } else { HEIGHT }
+    }
+
+    pub fn is_hires(&self) -> bool {
+        self.hires
+    }
+
+    /// Switches the resolution, which also clears both planes
+    pub fn set_hires(&mut self, hires: bool) {
+        self.hires = hires;
+        self.memory = vec![false; self.width() * self.height()];
+        self.plane2 = vec![false; self.width() * self.height()];
+    }
+
+    pub fn selected_planes(&self) -> u8 {
+        self.selected_planes
+    }
+
+    /// XO-CHIP FN01 - bit 0 selects `memory`, bit 1 selects `plane2`
+    pub fn select_planes(&mut self, mask: u8) {
+        self.selected_planes = mask & 0b11;
+    }
+
+    fn selected_mut(&mut self) -> Vec<&mut Vec<bool>> {
+        let mut planes = vec![];
+        if self.selected_planes & 0b01 != 0 { planes.push(&mut self.memory); }
+        if self.selected_planes & 0b10 != 0 { planes.push(&mut self.plane2); }
+        planes
+    }
+
+    /// Clears the selected planes
     pub fn cls(&mut self) {
-        self.memory = [false; WIDTH * HEIGHT]
+        for plane in self.selected_mut() {
+            plane.fill(false);
+        }
     } 
 
-    pub fn draw(&mut self, x: u8, y:u8) -> bool {
-        self.memory[x as usize + (y as usize * WIDTH)] ^= true;
+    /// Flips a pixel of `plane` (0 or 1), regardless of the selection
+    pub fn draw(&mut self, plane: usize, x: usize, y: usize) -> bool {
+        let width = self.width();
+        let pixels = if plane == 0 { &mut self.memory } else { &mut self.plane2 };
+        pixels[x + (y * width)] ^= true;
 
         // whether the pixel was turned off (v[0xF] register)
-        !self.memory[x as usize + (y as usize * WIDTH)]
-    }
-    
-    pub fn print_to_console(&mut self) {
-        let on: char = '█';
-        let off: char = ' ';
-
-        let mut stdout = std::io::stdout();
-
-        for y in 0..HEIGHT {
-            for x in 0..WIDTH {
-                let pixel: char = if self.memory[y * WIDTH + x] {on} else {off};
-                crossterm::queue!(stdout,
-                    MoveTo(x as u16, y as u16),
-                    Print(pixel),
-                ).unwrap();
+        !pixels[x + (y * width)]
+    }
+
+    /// Moves the picture `rows` pixels down, the top is filled with blank pixels
+    pub fn scroll_down(&mut self, rows: usize) {
+        let width = self.width();
+        for plane in self.selected_mut() {
+            let shift = (rows * width).min(plane.len());
+            plane.rotate_right(shift);
+            plane[..shift].fill(false);
+        }
+    }
+
+    /// Moves the picture `rows` pixels up, the bottom is filled with blank pixels
+    pub fn scroll_up(&mut self, rows: usize) {
+        let width = self.width();
+        for plane in self.selected_mut() {
+            let shift = (rows * width).min(plane.len());
+            let len = plane.len();
+            plane.rotate_left(shift);
+            plane[len - shift..].fill(false);
+        }
+    }
+
+    /// Moves the picture `columns` pixels left, the right edge is filled with blank pixels
+    pub fn scroll_left(&mut self, columns: usize) {
+        let width = self.width();
+        let columns = columns.min(width);
+        for plane in self.selected_mut() {
+            for row in plane.chunks_mut(width) {
+                row.rotate_left(columns);
+                row[width - columns..].fill(false);
             }
         }
+    }
 
-        // Make changes visible on the screen
-        stdout.flush().unwrap();
+    /// Moves the picture `columns` pixels right, the left edge is filled with blank pixels
+    pub fn scroll_right(&mut self, columns: usize) {
+        let width = self.width();
+        let columns = columns.min(width);
+        for plane in self.selected_mut() {
+            for row in plane.chunks_mut(width) {
+                row.rotate_right(columns);
+                row[..columns].fill(false);
+            }
+        }
     }
 
-    /// # Examples
-    /// 
-    /// ```sh
-    /// # Set env variables
-    /// $   export CHIP8_ON='170000040'
+    /// The picture, not the palette
+    pub(crate) fn save_state(&self, state: &mut StateWriter) {
+        state.bool(self.hires);
+        state.u8(self.selected_planes);
+        state.bools(&self.memory);
+        state.bools(&self.plane2);
+    }
+
+    pub(crate) fn load_state(&mut self, state: &mut StateReader) -> Result<(), Chip8Error> {
+        self.hires = state.bool()?;
+        self.selected_planes = state.u8()? & 0b11;
+        self.memory = state.bools()?;
+        self.plane2 = state.bools()?;
+
+        let size = self.width() * self.height();
+        if self.memory.len() != size || self.plane2.len() != size {
+            return Err(invalid("the picture size does not match the resolution"));
+        }
+        Ok(())
+    }
+
+    /// Colour index of a pixel - bit 0 from `memory`, bit 1 from `plane2`
+    pub fn pixel(&self, index: usize) -> u8 {
+        self.memory[index] as u8 | (self.plane2[index] as u8) << 1
+    }
+
+    /// Both planes composited into four colours of [`Display::palette`]
+    pub fn get_screen_buffer(&self) -> Vec<u32> {
+        let colours = self.palette.colours();
+
+        (0..self.memory.len()).map(|index| colours[self.pixel(index) as usize]).collect()
+    }
+}
+
+/// Colours of the four pixel states, `0x00RRGGBB`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Palette {
+    pub off: u32,
+    pub on: u32,
+    /// XO-CHIP pixel set only on the second plane
+    pub plane2: u32,
+    /// XO-CHIP pixel set on both planes
+    pub blend: u32,
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Palette {
+            off : OFF,
+            on : ON,
+            plane2 : PLANE2,
+            blend : BLEND,
+        }
+    }
+}
+
+impl Palette {
+    /// Ordered by [`Display::pixel`]
+    pub fn colours(&self) -> [u32; 4] {
+        [self.off, self.on, self.plane2, self.blend]
+    }
+
+    /// Replaces the colours set in the environment
     ///
+    /// ```sh
     /// $   export CHIP8_ON='16711400'
-    /// 
-    /// # And then..
-    /// $   cargo run
+    /// $   export CHIP8_OFF=$((0x6667ab))
+    ///
+    /// # XO-CHIP only: second plane and both planes
+    /// $   export CHIP8_PLANE2='5592405'
+    /// $   export CHIP8_BLEND='11184810'
     /// ```
-    pub fn get_screen_buffer(&self) -> [u32; WIDTH * HEIGHT] {
-        let on: u32 = if let Ok(val) = env::var("CHIP8_ON") {
-            val.parse().unwrap_or(ON)
-        } else { ON };
-
-        let off: u32 = if let Ok(val) = env::var("CHIP8_OFF") {
-            val.parse().unwrap_or(OFF)
-        } else { OFF };
-
-        self.memory.map(|pixel| if pixel { on } else { off })
-    }
-
-    pub fn create_window() -> Window {
-        let mut window = Window::new(
-            "Chip8 - Rust",
-            self::WIDTH,
-            self::HEIGHT,
-            WindowOptions {
-                scale: minifb::Scale::X8,
-                borderless: false,
-                title: true,
-                resize: true,
-                scale_mode: minifb::ScaleMode::Stretch,
-                topmost: true,
-                transparency: false,
-                none: false,
-            },
-        ).unwrap();
-
-        window.set_position(20, 20);
-        // do not set a framerate limit for the display
-        // but rather do it for the CPU
-        window.limit_update_rate(None);
-        window
+    pub fn with_env(self) -> Self {
+        Palette {
+            off : env_colour("CHIP8_OFF", self.off),
+            on : env_colour("CHIP8_ON", self.on),
+            plane2 : env_colour("CHIP8_PLANE2", self.plane2),
+            blend : env_colour("CHIP8_BLEND", self.blend),
+        }
     }
 }
 
+fn env_colour(name: &str, default: u32) -> u32 {
+    if let Ok(val) = env::var(name) {
+        val.parse().unwrap_or(default)
+    } else { default }
+}
+
 pub const FONT_SET: [u8; 80] = [
     0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
     0x20, 0x60, 0x20, 0x20, 0x70, // 1
@@ -122,3 +251,26 @@ pub const FONT_SET: [u8; 80] = [
     0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
     0xF0, 0x80, 0xF0, 0x80, 0x80  // F
 ];
+
+/// Address of [`BIG_FONT_SET`] in memory, right after [`FONT_SET`]
+pub const BIG_FONT_ADDRESS: usize = FONT_SET.len();
+
+/// SUPER-CHIP 8x10 digits, A-F as extended by XO-CHIP
+pub const BIG_FONT_SET: [u8; 160] = [
+    0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, // 0
+    0x18, 0x78, 0x78, 0x18, 0x18, 0x18, 0x18, 0x18, 0xFF, 0xFF, // 1
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // 2
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 3
+    0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0x03, 0x03, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 5
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 6
+    0xFF, 0xFF, 0x03, 0x03, 0x06, 0x0C, 0x18, 0x18, 0x18, 0x18, // 7
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 8
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 9
+    0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+    0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, // B
+    0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C, // C
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // E
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0  // F
+];
@@ -0,0 +1,169 @@
+use std::fs;
+use std::path::Path;
+
+/// Produces the keypad state once per frame - bit `n` set means key `n` is held down
+pub trait InputSource {
+    fn poll(&mut self) -> u16;
+}
+
+/// No keys, ever - for headless runs
+pub struct NullInput;
+
+impl InputSource for NullInput {
+    fn poll(&mut self) -> u16 {
+        0
+    }
+}
+
+/// Keyboard characters bound to the 16 hex keys
+///
+/// The default lays the COSMAC VIP keypad over the left of a QWERTY keyboard:
+///
+/// ```text
+/// 1 2 3 C        1 2 3 4
+/// 4 5 6 D   <-   q w e r
+/// 7 8 9 E        a s d f
+/// A 0 B F        z x c v
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Keymap {
+    /// the position is the hex key
+    pub keys: [char; 16],
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Keymap {
+            keys : [
+                        'x', '1', '2', '3',
+                        'q', 'w', 'e', 'a',
+                        's', 'd', 'z', 'c',
+                        '4', 'r', 'f', 'v'
+                    ],
+        }
+    }
+}
+
+impl Keymap {
+    /// Reads 16 characters bound to the keys 0 to F in order, whitespace is skipped
+    ///
+    /// The default is `x123 qwea sdzc 4rfv`.
+    pub fn parse(layout: &str) -> Result<Self, String> {
+        let chars: Vec<char> = layout.chars()
+            .filter(|c| !c.is_whitespace())
+            .map(|c| c.to_ascii_lowercase())
+            .collect();
+
+        let keys: [char; 16] = chars.try_into()
+            .map_err(|chars: Vec<char>| format!("expected 16 keys, got {}", chars.len()))?;
+        if let Some(c) = keys.iter().enumerate().find_map(|(i, c)| keys[..i].contains(c).then_some(c)) {
+            return Err(format!("\"{}\" is bound twice", c));
+        }
+        Ok(Keymap { keys })
+    }
+
+    /// Maps a keyboard character to the hex key bound to it
+    pub fn key_index(&self, c: char) -> Option<u8> {
+        let c = c.to_ascii_lowercase();
+        self.keys.iter().position(|ch| *ch == c).map(|position| position as u8)
+    }
+}
+
+/// Replays keypad states from a script, then releases every key
+///
+/// Every line holds a frame count and the hex keys held for that many frames,
+/// `-` for none. Empty lines and `#` comments are skipped.
+///
+/// ```text
+/// # wait a second, then hold 5 and A for 10 frames
+/// 60 -
+/// 10 5A
+/// ```
+pub struct ScriptedInput {
+    /// keypad state of every frame
+    frames: Vec<u16>,
+    position: usize,
+}
+
+impl ScriptedInput {
+    pub fn new(frames: Vec<u16>) -> Self {
+        ScriptedInput { frames, position: 0 }
+    }
+
+    pub fn parse(script: &str) -> Result<Self, String> {
+        let mut frames = vec![];
+
+        for (number, line) in script.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let mut fields = line.split_whitespace();
+            let count: usize = fields.next()
+                .and_then(|count| count.parse().ok())
+                .ok_or(format!("line {}: expected a frame count", number + 1))?;
+
+            let keys = match fields.next() {
+                None | Some("-") => 0,
+                Some(keys) => keys.chars().try_fold(0u16, |state, key| {
+                    key.to_digit(16)
+                        .map(|key| state | 1 << key)
+                        .ok_or(format!("line {}: \"{}\" is not a hex key", number + 1, key))
+                })?,
+            };
+            frames.extend(std::iter::repeat_n(keys, count));
+        }
+        Ok(Self::new(frames))
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, String> {
+        let script = fs::read_to_string(&path)
+            .map_err(|error| format!("{}: {}", path.as_ref().display(), error))?;
+        Self::parse(&script)
+    }
+
+    /// Every scripted frame has been played
+    pub fn is_finished(&self) -> bool {
+        self.position >= self.frames.len()
+    }
+}
+
+impl InputSource for ScriptedInput {
+    fn poll(&mut self) -> u16 {
+        let keys = self.frames.get(self.position).copied().unwrap_or(0);
+        self.position += 1;
+        keys
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{InputSource, Keymap, ScriptedInput};
+
+    #[test]
+    fn it_maps_keys_to_hex() {
+        let keymap = Keymap::default();
+
+        assert_eq!(keymap.key_index('1'), Some(0x1), "wrong key mapped");
+        assert_eq!(keymap.key_index('4'), Some(0xC), "wrong key mapped");
+        assert_eq!(keymap.key_index('x'), Some(0x0), "wrong key mapped");
+        assert_eq!(keymap.key_index('V'), Some(0xF), "wrong key mapped");
+        assert_eq!(keymap.key_index('p'), None, "unbound key mapped");
+
+        assert_eq!(Keymap::parse("X123 qwea sdzc 4rfv"), Ok(keymap), "layout parsed wrong");
+        assert!(Keymap::parse("123").is_err(), "short layout accepted");
+        assert!(Keymap::parse("1123 qwea sdzc 4rfv").is_err(), "duplicate key accepted");
+    }
+
+    #[test]
+    fn it_replays_a_script() {
+        let mut input = ScriptedInput::parse("# comment\n2 -\n1 5a\n\n1 0").unwrap();
+
+        let states: Vec<u16> = (0..5).map(|_| input.poll()).collect();
+        assert_eq!(states, vec![0, 0, 1 << 0x5 | 1 << 0xA, 1, 0], "wrong states replayed");
+        assert!(input.is_finished(), "script not finished");
+
+        assert!(ScriptedInput::parse("1 G").is_err(), "invalid key accepted");
+    }
+}
@@ -0,0 +1,236 @@
+//! Decoded instructions, shared by the CPU and the disassembler
+
+use super::platform::Platform;
+
+/// One instruction with its operands, `x` and `y` being register numbers
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Instruction {
+    /// 00E0
+    Cls,
+    /// 00EE
+    Ret,
+    /// 00CN
+    ScrollDown(u8),
+    /// 00DN, XO-CHIP
+    ScrollUp(u8),
+    /// 00FB
+    ScrollRight,
+    /// 00FC
+    ScrollLeft,
+    /// 00FD
+    Exit,
+    /// 00FE
+    Lores,
+    /// 00FF
+    Hires,
+    /// 1NNN
+    Jump(u16),
+    /// 2NNN
+    Call(u16),
+    /// 3XKK
+    SkipEqByte { x: usize, kk: u8 },
+    /// 4XKK
+    SkipNeByte { x: usize, kk: u8 },
+    /// 5XY0
+    SkipEq { x: usize, y: usize },
+    /// 5XY2, XO-CHIP
+    SaveRange { x: usize, y: usize },
+    /// 5XY3, XO-CHIP
+    LoadRange { x: usize, y: usize },
+    /// 6XKK
+    LoadByte { x: usize, kk: u8 },
+    /// 7XKK
+    AddByte { x: usize, kk: u8 },
+    /// 8XY0
+    Load { x: usize, y: usize },
+    /// 8XY1
+    Or { x: usize, y: usize },
+    /// 8XY2
+    And { x: usize, y: usize },
+    /// 8XY3
+    Xor { x: usize, y: usize },
+    /// 8XY4
+    Add { x: usize, y: usize },
+    /// 8XY5
+    Sub { x: usize, y: usize },
+    /// 8XY6
+    Shr { x: usize, y: usize },
+    /// 8XY7
+    SubN { x: usize, y: usize },
+    /// 8XYE
+    Shl { x: usize, y: usize },
+    /// 9XY0
+    SkipNe { x: usize, y: usize },
+    /// ANNN
+    LoadI(u16),
+    /// BNNN, `x` is added instead of V0 under the jump quirk
+    JumpOffset { x: usize, nnn: u16 },
+    /// CXKK
+    Random { x: usize, kk: u8 },
+    /// DXYN, a 16x16 sprite when `n` is 0
+    Draw { x: usize, y: usize, n: u8 },
+    /// EX9E
+    SkipKey(usize),
+    /// EXA1
+    SkipNotKey(usize),
+    /// F000 NNNN, XO-CHIP - the address is the next word
+    LoadLongI,
+    /// FN01, XO-CHIP
+    Plane(u8),
+    /// F002, XO-CHIP
+    Audio,
+    /// FX07
+    GetDelay(usize),
+    /// FX0A
+    WaitKey(usize),
+    /// FX15
+    SetDelay(usize),
+    /// FX18
+    SetSound(usize),
+    /// FX1E
+    AddI(usize),
+    /// FX29
+    Font(usize),
+    /// FX30
+    BigFont(usize),
+    /// FX33
+    Bcd(usize),
+    /// FX3A, XO-CHIP
+    Pitch(usize),
+    /// FX55
+    Store(usize),
+    /// FX65
+    Read(usize),
+    /// FX75
+    SaveFlags(usize),
+    /// FX85
+    LoadFlags(usize),
+}
+
+impl Instruction {
+    /// The instruction `opcode` stands for, `None` when the platform does not define it
+    pub fn decode(opcode: u16, platform: Platform) -> Option<Self> {
+        let x = ((opcode & 0x0F00) >> 8) as usize;
+        let y = ((opcode & 0x00F0) >> 4) as usize;
+        let n = (opcode & 0x000F) as u8;
+        let kk = (opcode & 0x00FF) as u8;
+        let nnn = opcode & 0x0FFF;
+        let schip = platform != Platform::Chip8;
+        let xo_chip = platform == Platform::XoChip;
+
+        let instruction = match (opcode >> 12, x, y, n) {
+            (0, 0, 0xE, 0) => Instruction::Cls,
+            (0, 0, 0xE, 0xE) => Instruction::Ret,
+            (0, 0, 0xC, _) if schip => Instruction::ScrollDown(n),
+            (0, 0, 0xD, _) if xo_chip => Instruction::ScrollUp(n),
+            (0, 0, 0xF, 0xB) if schip => Instruction::ScrollRight,
+            (0, 0, 0xF, 0xC) if schip => Instruction::ScrollLeft,
+            (0, 0, 0xF, 0xD) if schip => Instruction::Exit,
+            (0, 0, 0xF, 0xE) if schip => Instruction::Lores,
+            (0, 0, 0xF, 0xF) if schip => Instruction::Hires,
+            (0x1, _, _, _) => Instruction::Jump(nnn),
+            (0x2, _, _, _) => Instruction::Call(nnn),
+            (0x3, _, _, _) => Instruction::SkipEqByte { x, kk },
+            (0x4, _, _, _) => Instruction::SkipNeByte { x, kk },
+            (0x5, _, _, 0) => Instruction::SkipEq { x, y },
+            (0x5, _, _, 2) if xo_chip => Instruction::SaveRange { x, y },
+            (0x5, _, _, 3) if xo_chip => Instruction::LoadRange { x, y },
+            (0x6, _, _, _) => Instruction::LoadByte { x, kk },
+            (0x7, _, _, _) => Instruction::AddByte { x, kk },
+            (0x8, _, _, 0) => Instruction::Load { x, y },
+            (0x8, _, _, 1) => Instruction::Or { x, y },
+            (0x8, _, _, 2) => Instruction::And { x, y },
+            (0x8, _, _, 3) => Instruction::Xor { x, y },
+            (0x8, _, _, 4) => Instruction::Add { x, y },
+            (0x8, _, _, 5) => Instruction::Sub { x, y },
+            (0x8, _, _, 6) => Instruction::Shr { x, y },
+            (0x8, _, _, 7) => Instruction::SubN { x, y },
+            (0x8, _, _, 0xE) => Instruction::Shl { x, y },
+            (0x9, _, _, 0) => Instruction::SkipNe { x, y },
+            (0xA, _, _, _) => Instruction::LoadI(nnn),
+            (0xB, _, _, _) => Instruction::JumpOffset { x, nnn },
+            (0xC, _, _, _) => Instruction::Random { x, kk },
+            (0xD, _, _, _) => Instruction::Draw { x, y, n },
+            (0xE, _, 0x9, 0xE) => Instruction::SkipKey(x),
+            (0xE, _, 0xA, 0x1) => Instruction::SkipNotKey(x),
+            (0xF, 0, 0, 0) if xo_chip => Instruction::LoadLongI,
+            (0xF, _, 0, 0x1) if xo_chip => Instruction::Plane(x as u8),
+            (0xF, 0, 0, 0x2) if xo_chip => Instruction::Audio,
+            (0xF, _, 0, 0x7) => Instruction::GetDelay(x),
+            (0xF, _, 0, 0xA) => Instruction::WaitKey(x),
+            (0xF, _, 0x1, 0x5) => Instruction::SetDelay(x),
+            (0xF, _, 0x1, 0x8) => Instruction::SetSound(x),
+            (0xF, _, 0x1, 0xE) => Instruction::AddI(x),
+            (0xF, _, 0x2, 0x9) => Instruction::Font(x),
+            (0xF, _, 0x3, 0x0) if schip => Instruction::BigFont(x),
+            (0xF, _, 0x3, 0x3) => Instruction::Bcd(x),
+            (0xF, _, 0x3, 0xA) if xo_chip => Instruction::Pitch(x),
+            (0xF, _, 0x5, 0x5) => Instruction::Store(x),
+            (0xF, _, 0x6, 0x5) => Instruction::Read(x),
+            (0xF, _, 0x7, 0x5) if schip => Instruction::SaveFlags(x),
+            (0xF, _, 0x8, 0x5) if schip => Instruction::LoadFlags(x),
+            _ => return None,
+        };
+        Some(instruction)
+    }
+
+    /// Size in bytes, 4 for `F000 NNNN` and 2 for everything else
+    pub fn size(&self) -> usize {
+        match self {
+            Instruction::LoadLongI => 4,
+            _ => 2,
+        }
+    }
+
+    /// Whether the instruction may skip the next one
+    pub fn is_skip(&self) -> bool {
+        matches!(self,
+            Instruction::SkipEqByte { .. } | Instruction::SkipNeByte { .. } | Instruction::SkipEq { .. }
+            | Instruction::SkipNe { .. } | Instruction::SkipKey(_) | Instruction::SkipNotKey(_))
+    }
+
+    /// Operation type reported by [`crate::machine::Machine::step`]
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Instruction::Cls | Instruction::ScrollDown(_) | Instruction::ScrollUp(_) | Instruction::ScrollRight
+            | Instruction::ScrollLeft | Instruction::Lores | Instruction::Hires | Instruction::Draw { .. }
+            | Instruction::Plane(_) => "Display",
+            Instruction::Ret | Instruction::Exit | Instruction::Jump(_) | Instruction::Call(_)
+            | Instruction::JumpOffset { .. } => "Flow",
+            Instruction::SkipEqByte { .. } | Instruction::SkipNeByte { .. } | Instruction::SkipEq { .. }
+            | Instruction::SkipNe { .. } => "Cond",
+            Instruction::LoadByte { .. } | Instruction::AddByte { .. } => "Const",
+            Instruction::Load { .. } => "Assig",
+            Instruction::Or { .. } | Instruction::And { .. } | Instruction::Xor { .. } | Instruction::Shr { .. }
+            | Instruction::Shl { .. } => "BitOp",
+            Instruction::Add { .. } | Instruction::Sub { .. } | Instruction::SubN { .. } => "Math",
+            Instruction::Random { .. } => "Rand",
+            Instruction::SkipKey(_) | Instruction::SkipNotKey(_) | Instruction::WaitKey(_) => "KeyOp",
+            Instruction::Audio | Instruction::SetSound(_) | Instruction::Pitch(_) => "Sound",
+            Instruction::GetDelay(_) | Instruction::SetDelay(_) => "Timer",
+            Instruction::Bcd(_) => "BCD",
+            Instruction::SaveRange { .. } | Instruction::LoadRange { .. } | Instruction::LoadI(_)
+            | Instruction::LoadLongI | Instruction::AddI(_) | Instruction::Font(_) | Instruction::BigFont(_)
+            | Instruction::Store(_) | Instruction::Read(_) | Instruction::SaveFlags(_)
+            | Instruction::LoadFlags(_) => "MEM",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Instruction;
+    use crate::components::platform::Platform;
+
+    #[test]
+    fn it_decodes_per_platform() {
+        assert_eq!(Instruction::decode(0xD125, Platform::Chip8), Some(Instruction::Draw { x: 1, y: 2, n: 5 }));
+        assert_eq!(Instruction::decode(0xB3A0, Platform::SuperChip), Some(Instruction::JumpOffset { x: 3, nnn: 0x3A0 }));
+        assert_eq!(Instruction::decode(0xF000, Platform::XoChip).map(|instruction| instruction.size()), Some(4), "long load is not 4 bytes");
+        assert_eq!(Instruction::decode(0xF000, Platform::Chip8), None, "XO-CHIP opcode on CHIP-8");
+        assert_eq!(Instruction::decode(0x5123, Platform::SuperChip), None, "XO-CHIP opcode on SUPER-CHIP");
+        assert_eq!(Instruction::decode(0x00FF, Platform::Chip8), None, "SUPER-CHIP opcode on CHIP-8");
+        assert_eq!(Instruction::decode(0x00FF, Platform::XoChip), Some(Instruction::Hires), "SUPER-CHIP opcode missing on XO-CHIP");
+        assert_eq!(Instruction::decode(0x0123, Platform::XoChip), None, "machine code routine decoded");
+    }
+}
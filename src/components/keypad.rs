@@ -1,10 +1,14 @@
-use std::io::stdout;
-use std::time::Duration;
-use crossterm::event::{read, Event, KeyCode, KeyEvent, KeyModifiers, poll};
-use crossterm::terminal::{enable_raw_mode, self};
+use crate::error::Chip8Error;
+use crate::state::{StateReader, StateWriter};
 
+/// State of the 16-key hexadecimal keypad, fed by an `InputSource` every frame
 pub struct Keypad {
-    keys: Vec<char>,
+    /// bit `n` set - key `n` is held down
+    state: u16,
+    /// `state` of the previous frame, to tell fresh presses apart
+    previous: u16,
+    /// key pressed during FX0A, waiting to be released
+    awaited: Option<u8>,
 }
 
 impl Default for Keypad {
@@ -16,41 +20,88 @@ impl Default for Keypad {
 impl Keypad {
     pub fn new() -> Self {
         Keypad {
-            keys : vec![
-                        '1', '2', '3', '4',
-                        'q', 'w', 'e', 'r',
-                        'a', 's', 'd', 'f',
-                        'z', 'x', 'c', 'v'
-                    ]
+            state : 0,
+            previous : 0,
+            awaited : None,
         }
     }
 
-    pub fn await_key_press(&self) -> u8 {
-        let mut _stdout = stdout();
-        if !terminal::is_raw_mode_enabled().unwrap() {
-            enable_raw_mode().unwrap();
-        }
+    /// Replaces the state of all keys, called once per frame
+    pub fn set_state(&mut self, state: u16) {
+        self.previous = self.state;
+        self.state = state;
+    }
+
+    pub fn state(&self) -> u16 {
+        self.state
+    }
+
+    pub fn is_pressed(&self, key: u8) -> bool {
+        key < 16 && self.state & (1 << key) != 0
+    }
+
+    /// Lowest key that went down since the previous frame
+    pub fn just_pressed(&self) -> Option<u8> {
+        let pressed = self.state & !self.previous;
+        (0..16).find(|key| pressed & (1 << key) != 0)
+    }
 
-        loop {
-            if let Event::Key(KeyEvent { code: KeyCode::Char(c), modifiers: KeyModifiers::NONE, .. }) = read().unwrap() {
-                return c as u8;
+    /// FX0A - a key counts once it has been pressed and released again, like on the VIP
+    ///
+    /// Returns `None` while still waiting, the instruction is then repeated.
+    pub fn await_key(&mut self) -> Option<u8> {
+        match self.awaited {
+            Some(key) if !self.is_pressed(key) => {
+                self.awaited = None;
+                Some(key)
+            }
+            Some(_) => None,
+            None => {
+                self.awaited = self.just_pressed();
+                None
             }
         }
     }
 
-    pub fn get_key_pressed(&self) -> Option<u8> {
-        let mut _stdout = stdout();
-        if !terminal::is_raw_mode_enabled().unwrap() {
-            enable_raw_mode().unwrap();
-        }
+    pub(crate) fn save_state(&self, state: &mut StateWriter) {
+        state.u16(self.state);
+        state.u16(self.previous);
+        // no key is 0xFF
+        state.u8(self.awaited.unwrap_or(0xFF));
+    }
+
+    pub(crate) fn load_state(&mut self, state: &mut StateReader) -> Result<(), Chip8Error> {
+        self.state = state.u16()?;
+        self.previous = state.u16()?;
+        self.awaited = Some(state.u8()?).filter(|key| *key < 16);
+        Ok(())
+    }
+
+    /// Forgets all keys and any FX0A in progress
+    pub fn reset(&mut self) {
+        self.state = 0;
+        self.previous = 0;
+        self.awaited = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Keypad;
+
+    #[test]
+    fn it_awaits_press_and_release() {
+        let mut keypad = Keypad::new();
+        // held since before the wait - not a fresh press
+        keypad.set_state(1 << 0x2);
+        keypad.set_state(1 << 0x2);
+        assert_eq!(keypad.await_key(), None, "held key accepted");
 
-        if poll(Duration::from_secs(0)).unwrap() {
-            if let Event::Key(KeyEvent { code: KeyCode::Char(c), modifiers: KeyModifiers::NONE, .. }) = read().unwrap() {
-                if self.keys.contains(&c) {
-                    return Some(self.keys.iter().position(|ch| *ch == c).unwrap() as u8 + 1)
-                }
-                Some(0)
-            } else { None }
-        } else { None }
+        keypad.set_state(1 << 0x2 | 1 << 0xA);
+        assert_eq!(keypad.await_key(), None, "key accepted before release");
+
+        keypad.set_state(1 << 0x2);
+        assert_eq!(keypad.await_key(), Some(0xA), "released key not accepted");
+        assert_eq!(keypad.await_key(), None, "key accepted twice");
     }
-}
\ No newline at end of file
+}
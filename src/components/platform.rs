@@ -0,0 +1,59 @@
+use super::quirks::Quirks;
+use crate::error::Chip8Error;
+use crate::state::{invalid, StateReader, StateWriter};
+
+/// CHIP-8 dialect a ROM was written for
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Platform {
+    /// original COSMAC VIP CHIP-8
+    #[default]
+    Chip8,
+    /// SUPER-CHIP 1.1
+    SuperChip,
+    /// Octo's XO-CHIP
+    XoChip,
+}
+
+impl Platform {
+    /// Names accepted by [`Platform::from_name`]
+    pub const NAMES: [&'static str; 3] = ["chip8", "schip", "xochip"];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "chip8" | "chip-8" => Some(Platform::Chip8),
+            "schip" | "superchip" | "super-chip" => Some(Platform::SuperChip),
+            "xochip" | "xo-chip" => Some(Platform::XoChip),
+            _ => None
+        }
+    }
+
+    /// Addressable memory in bytes
+    pub fn memory_size(&self) -> usize {
+        match self {
+            Platform::Chip8 | Platform::SuperChip => 0x1000,
+            Platform::XoChip => 0x10000,
+        }
+    }
+
+    pub(crate) fn save_state(&self, state: &mut StateWriter) {
+        state.u8(*self as u8);
+    }
+
+    pub(crate) fn load_state(&mut self, state: &mut StateReader) -> Result<(), Chip8Error> {
+        *self = match state.u8()? {
+            0 => Platform::Chip8,
+            1 => Platform::SuperChip,
+            2 => Platform::XoChip,
+            platform => return Err(invalid(&format!("platform {} is unknown", platform))),
+        };
+        Ok(())
+    }
+
+    pub fn default_quirks(&self) -> Quirks {
+        match self {
+            Platform::Chip8 => Quirks::chip8(),
+            Platform::SuperChip => Quirks::schip(),
+            Platform::XoChip => Quirks::xochip(),
+        }
+    }
+}
@@ -0,0 +1,174 @@
+use crate::error::Chip8Error;
+use crate::state::{invalid, StateReader, StateWriter};
+use super::stack::MAX_DEPTH;
+
+/// How FX55/FX65 change I after the transfer
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MemoryIncrement {
+    /// I is left untouched
+    None,
+    /// I += X (CHIP-48 off-by-one)
+    X,
+    /// I += X + 1 - I points right after the last register
+    XPlusOne,
+}
+
+/// What a call on a full stack or a return on an empty one does
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StackPolicy {
+    /// stop with an error
+    Error,
+    /// break into the debugger on the offending instruction
+    Trap,
+    /// the stack pointer wraps around, overwriting or reading stale entries
+    Wrap,
+}
+
+impl StackPolicy {
+    /// Names accepted by [`StackPolicy::from_name`]
+    pub const NAMES: [&'static str; 3] = ["error", "trap", "wrap"];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "error" => Some(StackPolicy::Error),
+            "trap" => Some(StackPolicy::Trap),
+            "wrap" => Some(StackPolicy::Wrap),
+            _ => None
+        }
+    }
+}
+
+/// Behaviour of the ambiguous instructions, which differ between CHIP-8 platforms
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Quirks {
+    /// 8XY6/8XYE shift VX in place instead of copying VY into VX first
+    pub shift: bool,
+    /// how far FX55/FX65 move I
+    pub memory_increment: MemoryIncrement,
+    /// BNNN is treated as BXNN and adds VX instead of V0
+    pub jump: bool,
+    /// 8XY1, 8XY2 and 8XY3 reset VF to 0
+    pub vf_reset: bool,
+    /// sprites are clipped at the screen edges instead of wrapping around
+    pub clipping: bool,
+    /// DXYN waits for the vertical blank - at most one sprite per frame
+    pub display_wait: bool,
+    /// nesting levels of 2NNN calls
+    pub stack_depth: usize,
+    /// what happens past either end of the stack
+    pub stack_policy: StackPolicy,
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Self::chip8()
+    }
+}
+
+impl Quirks {
+    /// Names accepted by [`Quirks::preset`]
+    pub const PRESETS: [&'static str; 4] = ["chip8", "chip48", "schip", "xochip"];
+
+    /// Original COSMAC VIP interpreter
+    pub fn chip8() -> Self {
+        Quirks {
+            shift: false,
+            memory_increment: MemoryIncrement::XPlusOne,
+            jump: false,
+            vf_reset: true,
+            clipping: true,
+            display_wait: true,
+            stack_depth: 12,
+            stack_policy: StackPolicy::Error,
+        }
+    }
+
+    /// CHIP-48 on the HP-48 calculators
+    pub fn chip48() -> Self {
+        Quirks {
+            shift: true,
+            memory_increment: MemoryIncrement::X,
+            jump: true,
+            vf_reset: false,
+            clipping: true,
+            display_wait: false,
+            stack_depth: 16,
+            stack_policy: StackPolicy::Error,
+        }
+    }
+
+    /// SUPER-CHIP 1.1
+    pub fn schip() -> Self {
+        Quirks {
+            shift: true,
+            memory_increment: MemoryIncrement::None,
+            jump: true,
+            vf_reset: false,
+            clipping: true,
+            display_wait: false,
+            stack_depth: 16,
+            stack_policy: StackPolicy::Error,
+        }
+    }
+
+    /// Octo's XO-CHIP
+    pub fn xochip() -> Self {
+        Quirks {
+            shift: false,
+            memory_increment: MemoryIncrement::XPlusOne,
+            jump: false,
+            vf_reset: false,
+            clipping: false,
+            display_wait: false,
+            stack_depth: 16,
+            stack_policy: StackPolicy::Trap,
+        }
+    }
+
+    pub(crate) fn save_state(&self, state: &mut StateWriter) {
+        state.bool(self.shift);
+        state.u8(self.memory_increment as u8);
+        state.bool(self.jump);
+        state.bool(self.vf_reset);
+        state.bool(self.clipping);
+        state.bool(self.display_wait);
+        state.u32(self.stack_depth as u32);
+        state.u8(self.stack_policy as u8);
+    }
+
+    pub(crate) fn load_state(&mut self, state: &mut StateReader) -> Result<(), Chip8Error> {
+        self.shift = state.bool()?;
+        self.memory_increment = match state.u8()? {
+            0 => MemoryIncrement::None,
+            1 => MemoryIncrement::X,
+            2 => MemoryIncrement::XPlusOne,
+            value => return Err(invalid(&format!("memory increment {} is unknown", value))),
+        };
+        self.jump = state.bool()?;
+        self.vf_reset = state.bool()?;
+        self.clipping = state.bool()?;
+        self.display_wait = state.bool()?;
+        self.stack_depth = state.u32()? as usize;
+        if self.stack_depth > MAX_DEPTH {
+            return Err(invalid(&format!("a stack of {} levels is too deep", self.stack_depth)));
+        }
+        self.stack_policy = match state.u8()? {
+            0 => StackPolicy::Error,
+            1 => StackPolicy::Trap,
+            2 => StackPolicy::Wrap,
+            value => return Err(invalid(&format!("stack policy {} is unknown", value))),
+        };
+        Ok(())
+    }
+
+    /// Looks a preset up by name, see [`Quirks::PRESETS`]
+    pub fn preset(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "chip8" | "chip-8" | "vip" => Some(Self::chip8()),
+            "chip48" | "chip-48" => Some(Self::chip48()),
+            "schip" | "superchip" | "super-chip" => Some(Self::schip()),
+            "xochip" | "xo-chip" => Some(Self::xochip()),
+            _ => None
+        }
+    }
+}
@@ -1,3 +1,6 @@
+use crate::error::Chip8Error;
+use crate::state::{StateReader, StateWriter};
+
 pub struct DoomRNG {
     rngtable: [u8; 256],
     index: u8,
@@ -18,9 +21,29 @@ impl DoomRNG {
     }
    
     pub fn change(&mut self) -> u8 {
-        self.index = (self.index + 1) % 0xFF;
+        // 255 is only reached by seeding, the sequence skips it otherwise
+        self.index = ((self.index as u16 + 1) % 0xFF) as u8;
         self.rngtable[self.index as usize]
     }
+
+    /// Position in the table, the next number comes from the one after it
+    pub fn seed(&self) -> u8 {
+        self.index
+    }
+
+    /// Restarts the sequence from the given position
+    pub fn set_seed(&mut self, seed: u8) {
+        self.index = seed;
+    }
+
+    pub(crate) fn save_state(&self, state: &mut StateWriter) {
+        state.u8(self.index);
+    }
+
+    pub(crate) fn load_state(&mut self, state: &mut StateReader) -> Result<(), Chip8Error> {
+        self.index = state.u8()?;
+        Ok(())
+    }
 }
 
 const RNG_TABLE: [u8; 256] = [
@@ -40,4 +63,21 @@ const RNG_TABLE: [u8; 256] = [
     109, 226, 71, 17, 161, 93, 186, 87, 244, 138, 20, 52, 123, 251, 26, 36,
     17, 46, 52, 231, 232, 76, 31, 221, 84, 37, 216, 165, 212, 106, 197, 242,
     98, 43, 39, 175, 254, 145, 190, 84, 118, 222, 187, 136, 120, 163, 236, 249
-];
\ No newline at end of file
+];
+
+#[cfg(test)]
+mod tests {
+    use super::DoomRNG;
+
+    #[test]
+    fn it_wraps_from_any_seed() {
+        let mut random = DoomRNG::new();
+        random.set_seed(254);
+        random.change();
+        assert_eq!(random.seed(), 0, "sequence changed");
+
+        random.set_seed(255);
+        random.change();
+        assert_eq!(random.seed(), 1, "last position does not wrap");
+    }
+}
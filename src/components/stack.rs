@@ -0,0 +1,143 @@
+use std::fmt;
+
+use crate::error::Chip8Error;
+use crate::state::{invalid, StateReader, StateWriter};
+
+/// Deepest stack a save state may hold, far beyond any platform's
+pub const MAX_DEPTH: usize = 0x1000;
+
+/// Fixed-depth call stack of return addresses
+///
+/// The slots form a ring, so the wrapping operations can go past either end
+/// the way an unchecked hardware stack pointer would.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Stack {
+    slots: Vec<u16>,
+    /// slot the next push goes to
+    top: usize,
+    /// entries pushed and not popped yet, at most `depth()`
+    len: usize,
+}
+
+impl Stack {
+    pub fn new(depth: usize) -> Self {
+        Stack {
+            slots : vec![0; depth.max(1)],
+            top : 0,
+            len : 0,
+        }
+    }
+
+    /// Nesting levels that fit
+    pub fn depth(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Pushes `address`, `false` when the stack is full
+    pub fn push(&mut self, address: u16) -> bool {
+        if self.len == self.depth() {
+            return false;
+        }
+        self.push_wrapping(address);
+        true
+    }
+
+    /// Pushes `address`, overwriting the oldest entry when the stack is full
+    pub fn push_wrapping(&mut self, address: u16) {
+        self.slots[self.top] = address;
+        self.top = (self.top + 1) % self.depth();
+        self.len = (self.len + 1).min(self.depth());
+    }
+
+    pub fn pop(&mut self) -> Option<u16> {
+        if self.is_empty() {
+            return None;
+        }
+        Some(self.pop_wrapping())
+    }
+
+    /// Pops an address, reading whatever the slot below holds when the stack is empty
+    pub fn pop_wrapping(&mut self) -> u16 {
+        self.top = (self.top + self.depth() - 1) % self.depth();
+        self.len = self.len.saturating_sub(1);
+        self.slots[self.top]
+    }
+
+    pub(crate) fn save_state(&self, state: &mut StateWriter) {
+        state.u32(self.depth() as u32);
+        state.u32(self.len as u32);
+        for address in self.entries() {
+            state.u16(address);
+        }
+    }
+
+    pub(crate) fn load_state(&mut self, state: &mut StateReader) -> Result<(), Chip8Error> {
+        let depth = state.u32()? as usize;
+        let len = state.u32()? as usize;
+        if depth > MAX_DEPTH {
+            return Err(invalid(&format!("a stack of {} levels is too deep", depth)));
+        }
+        if len > depth {
+            return Err(invalid("the stack holds more than its depth"));
+        }
+        *self = Stack::new(depth);
+        for _ in 0..len {
+            self.push(state.u16()?);
+        }
+        Ok(())
+    }
+
+    /// Entries from the oldest to the latest
+    pub fn entries(&self) -> Vec<u16> {
+        (0..self.len)
+            .map(|offset| self.slots[(self.top + self.depth() - self.len + offset) % self.depth()])
+            .collect()
+    }
+}
+
+impl fmt::Debug for Stack {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.entries()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Stack;
+
+    #[test]
+    fn it_bounds_the_depth() {
+        let mut stack = Stack::new(2);
+
+        assert!(stack.push(0x202), "push refused");
+        assert!(stack.push(0x204), "push refused");
+        assert!(!stack.push(0x206), "push past the depth");
+        assert_eq!(stack.entries(), vec![0x202, 0x204], "wrong entries");
+
+        assert_eq!(stack.pop(), Some(0x204), "wrong address popped");
+        assert_eq!(stack.pop(), Some(0x202), "wrong address popped");
+        assert_eq!(stack.pop(), None, "empty stack popped");
+    }
+
+    #[test]
+    fn it_wraps_around() {
+        let mut stack = Stack::new(2);
+
+        stack.push_wrapping(0x202);
+        stack.push_wrapping(0x204);
+        stack.push_wrapping(0x206);
+        assert_eq!(stack.entries(), vec![0x204, 0x206], "oldest entry not overwritten");
+
+        stack.pop();
+        stack.pop();
+        assert_eq!(stack.pop_wrapping(), 0x206, "underflow does not wrap");
+    }
+}
@@ -0,0 +1,280 @@
+//! Settings read once at startup from `config.toml` in the XDG config directory
+//!
+//! Every key is optional. Sections under `[roms]` are keyed by the ROM hash
+//! (as printed by `chip8-rs info`) and override the top level for that ROM.
+//!
+//! ```toml
+//! speed = 15
+//! platform = "schip"
+//! quirks = "chip48"
+//! # the keys 0 to F in order
+//! keymap = "x123 qwea sdzc 4rfv"
+//! unknown_opcodes = "warn"
+//! stack_depth = 16
+//! stack_policy = "wrap"
+//! autosave = true
+//! rewind = 16
+//! reload = "keep"
+//! rom_dirs = ["roms/", "/home/me/chip8"]
+//!
+//! [palette]
+//! on = 0xFFCC00
+//! off = 0x202020
+//!
+//! [window]
+//! scale = 8
+//! x = 20
+//! y = 20
+//! topmost = true
+//!
+//! [roms.64e45391ba0238a1]
+//! speed = 30
+//! palette = { on = 0x00FF00 }
+//! ```
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Deserializer};
+use serde::de::Error;
+
+use crate::components::display::Palette;
+use crate::components::input::Keymap;
+use crate::components::platform::Platform;
+use crate::components::quirks::{Quirks, StackPolicy};
+use crate::components::stack::MAX_DEPTH;
+use crate::machine::{ReloadMode, UnknownOpcodePolicy};
+use crate::rom;
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    #[serde(flatten)]
+    pub settings: Settings,
+    pub window: WindowConfig,
+    /// directories the ROM selection menu lists
+    pub rom_dirs: Vec<PathBuf>,
+    /// overrides keyed by the hex ROM hash
+    pub roms: HashMap<String, Settings>,
+}
+
+/// Settings that can be overridden per ROM
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// instructions per frame
+    pub speed: Option<usize>,
+    #[serde(deserialize_with = "platform")]
+    pub platform: Option<Platform>,
+    #[serde(deserialize_with = "quirks")]
+    pub quirks: Option<Quirks>,
+    #[serde(deserialize_with = "keymap")]
+    pub keymap: Option<Keymap>,
+    pub palette: PaletteConfig,
+    /// ignore, warn, halt or break
+    #[serde(deserialize_with = "unknown_opcodes")]
+    pub unknown_opcodes: Option<UnknownOpcodePolicy>,
+    /// call stack levels
+    #[serde(deserialize_with = "stack_depth")]
+    pub stack_depth: Option<usize>,
+    /// error, trap or wrap
+    #[serde(deserialize_with = "stack_policy")]
+    pub stack_policy: Option<StackPolicy>,
+    /// save the state on quit, resume from it on launch
+    pub autosave: Option<bool>,
+    /// memory for rewinding in MiB
+    pub rewind: Option<usize>,
+    /// reset or keep, what a reload of the watched ROM keeps
+    #[serde(deserialize_with = "reload")]
+    pub reload: Option<ReloadMode>,
+}
+
+/// Colours replacing the [`Palette`] defaults
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct PaletteConfig {
+    pub off: Option<u32>,
+    pub on: Option<u32>,
+    pub plane2: Option<u32>,
+    pub blend: Option<u32>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct WindowConfig {
+    /// pixel size: 1, 2, 4, 8, 16 or 32
+    pub scale: usize,
+    pub x: isize,
+    pub y: isize,
+    /// keep the window above the others
+    pub topmost: bool,
+}
+
+impl Default for WindowConfig {
+    fn default() -> Self {
+        WindowConfig {
+            scale : 8,
+            x : 20,
+            y : 20,
+            topmost : true,
+        }
+    }
+}
+
+impl Config {
+    /// `$XDG_CONFIG_HOME/chip8-rs/config.toml` or the platform's equivalent
+    pub fn path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("chip8-rs").join("config.toml"))
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let config: Config = toml::from_str(text).map_err(|error| error.to_string())?;
+
+        if ![1, 2, 4, 8, 16, 32].contains(&config.window.scale) {
+            return Err(format!("window scale {} is not one of 1, 2, 4, 8, 16 or 32", config.window.scale));
+        }
+        Ok(config)
+    }
+
+    /// Reads the config at `path`, a missing file gives the defaults
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, String> {
+        match fs::read_to_string(&path) {
+            Ok(text) => Self::parse(&text).map_err(|error| format!("{}: {}", path.as_ref().display(), error)),
+            Err(error) if error.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(error) => Err(format!("{}: {}", path.as_ref().display(), error)),
+        }
+    }
+
+    /// The top level settings with the section of `rom` on top
+    pub fn for_rom(&self, rom: &[u8]) -> Settings {
+        let hash = format!("{:016x}", rom::hash(rom));
+
+        match self.roms.iter().find(|(key, _)| key.eq_ignore_ascii_case(&hash)) {
+            Some((_, overrides)) => self.settings.merge(overrides),
+            None => self.settings.clone(),
+        }
+    }
+}
+
+impl Settings {
+    /// `overrides` wins wherever it is set
+    pub fn merge(&self, overrides: &Settings) -> Settings {
+        Settings {
+            speed : overrides.speed.or(self.speed),
+            platform : overrides.platform.or(self.platform),
+            quirks : overrides.quirks.or(self.quirks),
+            keymap : overrides.keymap.or(self.keymap),
+            unknown_opcodes : overrides.unknown_opcodes.or(self.unknown_opcodes),
+            stack_depth : overrides.stack_depth.or(self.stack_depth),
+            stack_policy : overrides.stack_policy.or(self.stack_policy),
+            autosave : overrides.autosave.or(self.autosave),
+            rewind : overrides.rewind.or(self.rewind),
+            reload : overrides.reload.or(self.reload),
+            palette : PaletteConfig {
+                off : overrides.palette.off.or(self.palette.off),
+                on : overrides.palette.on.or(self.palette.on),
+                plane2 : overrides.palette.plane2.or(self.palette.plane2),
+                blend : overrides.palette.blend.or(self.palette.blend),
+            },
+        }
+    }
+}
+
+impl PaletteConfig {
+    /// `palette` with the configured colours replaced
+    pub fn apply(&self, palette: Palette) -> Palette {
+        Palette {
+            off : self.off.unwrap_or(palette.off),
+            on : self.on.unwrap_or(palette.on),
+            plane2 : self.plane2.unwrap_or(palette.plane2),
+            blend : self.blend.unwrap_or(palette.blend),
+        }
+    }
+}
+
+fn platform<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Platform>, D::Error> {
+    let name = String::deserialize(deserializer)?;
+    Platform::from_name(&name)
+        .map(Some)
+        .ok_or(D::Error::custom(format!("platform \"{}\" is not one of {:?}", name, Platform::NAMES)))
+}
+
+fn quirks<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Quirks>, D::Error> {
+    let name = String::deserialize(deserializer)?;
+    Quirks::preset(&name)
+        .map(Some)
+        .ok_or(D::Error::custom(format!("quirks \"{}\" is not one of {:?}", name, Quirks::PRESETS)))
+}
+
+fn unknown_opcodes<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<UnknownOpcodePolicy>, D::Error> {
+    let name = String::deserialize(deserializer)?;
+    UnknownOpcodePolicy::from_name(&name)
+        .map(Some)
+        .ok_or(D::Error::custom(format!("unknown_opcodes \"{}\" is not one of {:?}", name, UnknownOpcodePolicy::NAMES)))
+}
+
+fn stack_depth<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<usize>, D::Error> {
+    let depth = usize::deserialize(deserializer)?;
+    if !(1..=MAX_DEPTH).contains(&depth) {
+        return Err(D::Error::custom(format!("stack_depth {} is not from 1 to {}", depth, MAX_DEPTH)));
+    }
+    Ok(Some(depth))
+}
+
+fn stack_policy<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<StackPolicy>, D::Error> {
+    let name = String::deserialize(deserializer)?;
+    StackPolicy::from_name(&name)
+        .map(Some)
+        .ok_or(D::Error::custom(format!("stack_policy \"{}\" is not one of {:?}", name, StackPolicy::NAMES)))
+}
+
+fn reload<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<ReloadMode>, D::Error> {
+    let name = String::deserialize(deserializer)?;
+    ReloadMode::from_name(&name)
+        .map(Some)
+        .ok_or(D::Error::custom(format!("reload \"{}\" is not one of {:?}", name, ReloadMode::NAMES)))
+}
+
+fn keymap<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Keymap>, D::Error> {
+    let layout = String::deserialize(deserializer)?;
+    Keymap::parse(&layout)
+        .map(Some)
+        .map_err(D::Error::custom)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Config;
+    use crate::components::{platform::Platform, quirks::Quirks};
+    use crate::rom;
+
+    #[test]
+    fn it_parses_a_config() {
+        let config = Config::parse("speed = 15\nquirks = \"schip\"\nrom_dirs = [\"roms/\"]\n\n[palette]\non = 0xFFCC00\n\n[window]\nscale = 4\n").unwrap();
+
+        assert_eq!(config.settings.speed, Some(15), "wrong speed");
+        assert_eq!(config.settings.quirks, Some(Quirks::schip()), "wrong quirks");
+        assert_eq!(config.settings.palette.on, Some(0xFFCC00), "wrong colour");
+        assert_eq!(config.window.scale, 4, "wrong scale");
+        assert!(config.window.topmost, "window default lost");
+
+        assert!(Config::parse("platform = \"nes\"").is_err(), "unknown platform accepted");
+        assert!(Config::parse("[window]\nscale = 3").is_err(), "odd scale accepted");
+        assert!(Config::parse("stack_depth = 0").is_err(), "empty stack accepted");
+        assert!(Config::parse("stack_depth = 4097").is_err(), "stack deeper than save states accepted");
+    }
+
+    #[test]
+    fn it_overrides_per_rom() {
+        let rom = [0x12, 0x00];
+        let text = format!("speed = 15\nplatform = \"schip\"\n\n[roms.{:016x}]\nspeed = 30\n", rom::hash(&rom));
+        let config = Config::parse(&text).unwrap();
+
+        let settings = config.for_rom(&rom);
+        assert_eq!(settings.speed, Some(30), "override ignored");
+        assert_eq!(settings.platform, Some(Platform::SuperChip), "top level lost");
+        assert_eq!(config.for_rom(&[0x00]).speed, Some(15), "override of another rom used");
+    }
+}
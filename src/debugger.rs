@@ -0,0 +1,399 @@
+//! Breakpoints, watchpoints and stepping on top of a [`Machine`]
+//!
+//! The [`Debugger`] runs the machine one instruction at a time, checking its
+//! breakpoints and watchpoints before each one. Frontends call
+//! [`Debugger::run_frame`] instead of [`Machine::run_frame`] while it is active.
+
+use std::fmt;
+use std::ops::RangeInclusive;
+
+use crate::components::{cpu::Cpu, instruction::Instruction};
+use crate::error::Chip8Error;
+use crate::machine::Machine;
+
+/// Register a breakpoint condition looks at
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Register {
+    V(u8),
+    I,
+    Dt,
+    St,
+}
+
+impl Register {
+    pub fn parse(name: &str) -> Result<Self, String> {
+        let name = name.to_lowercase();
+        match name.as_str() {
+            "i" => Ok(Register::I),
+            "dt" => Ok(Register::Dt),
+            "st" => Ok(Register::St),
+            _ => name.strip_prefix('v')
+                .and_then(|index| u8::from_str_radix(index, 16).ok())
+                .filter(|index| *index < 16)
+                .map(Register::V)
+                .ok_or(format!("\"{}\" is not a register", name)),
+        }
+    }
+
+    pub fn value(&self, cpu: &Cpu) -> u16 {
+        match self {
+            Register::V(index) => cpu.v[*index as usize] as u16,
+            Register::I => cpu.i,
+            Register::Dt => cpu.dt as u16,
+            Register::St => cpu.st as u16,
+        }
+    }
+}
+
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Register::V(index) => write!(f, "V{:X}", index),
+            Register::I => write!(f, "I"),
+            Register::Dt => write!(f, "DT"),
+            Register::St => write!(f, "ST"),
+        }
+    }
+}
+
+/// Register compared with a value, e.g. `v3 == 0x10`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Condition {
+    pub register: Register,
+    /// `==`, `!=`, `<`, `<=`, `>` or `>=`
+    pub comparison: &'static str,
+    pub value: u16,
+}
+
+impl Condition {
+    const COMPARISONS: [&'static str; 6] = ["==", "!=", "<=", ">=", "<", ">"];
+
+    /// Reads `REGISTER OP VALUE`, the spaces are optional
+    pub fn parse(condition: &str) -> Result<Self, String> {
+        let condition: String = condition.split_whitespace().collect();
+        let (position, comparison) = Self::COMPARISONS.iter()
+            .find_map(|comparison| condition.find(comparison).map(|position| (position, *comparison)))
+            .ok_or(format!("\"{}\" compares nothing, use one of {}", condition, Self::COMPARISONS.join(" ")))?;
+
+        Ok(Condition {
+            register : Register::parse(&condition[..position])?,
+            comparison,
+            value : parse_number(&condition[position + comparison.len()..])?,
+        })
+    }
+
+    pub fn holds(&self, cpu: &Cpu) -> bool {
+        let register = self.register.value(cpu);
+        match self.comparison {
+            "==" => register == self.value,
+            "!=" => register != self.value,
+            "<=" => register <= self.value,
+            ">=" => register >= self.value,
+            "<" => register < self.value,
+            _ => register > self.value,
+        }
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {:#X}", self.register, self.comparison, self.value)
+    }
+}
+
+/// Reads a number, hexadecimal with a `0x` prefix and decimal otherwise
+pub fn parse_number(number: &str) -> Result<u16, String> {
+    let parsed = match number.strip_prefix("0x").or(number.strip_prefix("0X")) {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => number.parse(),
+    };
+    parsed.map_err(|_| format!("\"{}\" is not a number", number))
+}
+
+/// Stops before the instruction at `address` is executed, if the condition holds
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Breakpoint {
+    pub address: u16,
+    pub condition: Option<Condition>,
+}
+
+/// Memory accesses a watchpoint stops on
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+impl Access {
+    /// `r`, `w` or `rw`
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "r" | "read" => Some(Access::Read),
+            "w" | "write" => Some(Access::Write),
+            "rw" | "readwrite" => Some(Access::ReadWrite),
+            _ => None
+        }
+    }
+
+    fn covers(&self, access: Access) -> bool {
+        *self == Access::ReadWrite || *self == access
+    }
+}
+
+/// Stops before an instruction that accesses memory in `range`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub range: RangeInclusive<u16>,
+    pub access: Access,
+}
+
+/// Why the debugger paused
+#[derive(Debug)]
+pub enum Stop {
+    /// the steps asked for are done
+    Step,
+    /// breakpoint number `n` was hit
+    Breakpoint(usize),
+    /// watchpoint number `n` was hit by the next instruction
+    Watchpoint { index: usize, address: u16, access: Access },
+    /// the machine set its `trap`
+    Trap(Chip8Error),
+    /// the program has exited
+    Exited,
+}
+
+/// How far to run before pausing again
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Run {
+    /// this many instructions
+    Steps(u32),
+    /// until the call stack is back at `depth` with the pc at `address`
+    Return { depth: usize, address: u16 },
+    /// until the call stack is shallower than `depth`
+    Out { depth: usize },
+    Continue,
+}
+
+/// Paused or running machine with breakpoints and watchpoints
+#[derive(Default)]
+pub struct Debugger {
+    pub breakpoints: Vec<Breakpoint>,
+    pub watchpoints: Vec<Watchpoint>,
+    /// `None` while paused
+    run: Option<Run>,
+    /// skip the checks once, so a resumed debugger moves past its breakpoint
+    resumed: bool,
+}
+
+impl Debugger {
+    /// A paused debugger
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.run.is_none()
+    }
+
+    pub fn pause(&mut self) {
+        self.run = None;
+    }
+
+    /// Runs `count` instructions
+    pub fn step(&mut self, count: u32) {
+        self.resume(Run::Steps(count.max(1)));
+    }
+
+    /// Steps, running a 2NNN call through to its return
+    pub fn step_over(&mut self, machine: &Machine) {
+        let cpu = &machine.cpu;
+        match cpu.opcode_at(cpu.pc).and_then(|opcode| Instruction::decode(opcode, cpu.platform)) {
+            Some(Instruction::Call(_)) => self.resume(Run::Return {
+                depth : cpu.stack.len(),
+                address : cpu.pc.wrapping_add(2),
+            }),
+            _ => self.step(1),
+        }
+    }
+
+    /// Runs until the current subroutine returns, `false` outside of one
+    pub fn step_out(&mut self, machine: &Machine) -> bool {
+        let depth = machine.cpu.stack.len();
+        if depth > 0 {
+            self.resume(Run::Out { depth });
+        }
+        depth > 0
+    }
+
+    /// Runs until a breakpoint, a watchpoint or a trap
+    pub fn resume_running(&mut self) {
+        self.resume(Run::Continue);
+    }
+
+    fn resume(&mut self, run: Run) {
+        self.run = Some(run);
+        self.resumed = true;
+    }
+
+    /// Runs the rest of the frame unless paused, returning why it paused
+    pub fn run_frame(&mut self, machine: &mut Machine, keys: u16) -> Result<Option<Stop>, Chip8Error> {
+        let Some(mut run) = self.run.take() else {
+            return Ok(None);
+        };
+        machine.set_keys(keys);
+
+        let frame = machine.frames;
+        while machine.frames == frame {
+            if machine.cpu.exited {
+                return Ok(Some(Stop::Exited));
+            }
+            if !std::mem::take(&mut self.resumed) {
+                if let Some(stop) = self.check(&machine.cpu) {
+                    return Ok(Some(stop));
+                }
+            }
+
+            machine.step()?;
+            if let Some(trap) = machine.trap.take() {
+                return Ok(Some(Stop::Trap(trap)));
+            }
+
+            let cpu = &machine.cpu;
+            let done = match &mut run {
+                Run::Steps(count) => {
+                    *count -= 1;
+                    *count == 0
+                }
+                Run::Return { depth, address } => cpu.stack.len() <= *depth && cpu.pc == *address,
+                Run::Out { depth } => cpu.stack.len() < *depth,
+                Run::Continue => false,
+            };
+            if done {
+                return Ok(Some(Stop::Step));
+            }
+        }
+        self.run = Some(run);
+        Ok(None)
+    }
+
+    /// Breakpoint or watchpoint hit by the next instruction
+    fn check(&self, cpu: &Cpu) -> Option<Stop> {
+        let breakpoint = self.breakpoints.iter().position(|breakpoint| {
+            breakpoint.address == cpu.pc && breakpoint.condition.is_none_or(|condition| condition.holds(cpu))
+        });
+        if let Some(index) = breakpoint {
+            return Some(Stop::Breakpoint(index));
+        }
+
+        let (access, range) = memory_access(cpu)?;
+        self.watchpoints.iter().enumerate().find_map(|(index, watchpoint)| {
+            let start = range.start().max(watchpoint.range.start());
+            let end = range.end().min(watchpoint.range.end());
+            (watchpoint.access.covers(access) && start <= end).then_some(Stop::Watchpoint { index, address: *start, access })
+        })
+    }
+}
+
+/// Memory the next instruction reads or writes, instruction fetches aside
+fn memory_access(cpu: &Cpu) -> Option<(Access, RangeInclusive<u16>)> {
+    let instruction = Instruction::decode(cpu.opcode_at(cpu.pc)?, cpu.platform)?;
+    let planes = cpu.display.selected_planes().count_ones() as u16;
+
+    let (access, len) = match instruction {
+        Instruction::SaveRange { x, y } => (Access::Write, x.abs_diff(y) as u16 + 1),
+        Instruction::LoadRange { x, y } => (Access::Read, x.abs_diff(y) as u16 + 1),
+        Instruction::Draw { n: 0, .. } => (Access::Read, planes * 32),
+        Instruction::Draw { n, .. } => (Access::Read, planes * n as u16),
+        Instruction::Audio => (Access::Read, 16),
+        Instruction::Bcd(_) => (Access::Write, 3),
+        Instruction::Store(x) => (Access::Write, x as u16 + 1),
+        Instruction::Read(x) => (Access::Read, x as u16 + 1),
+        _ => return None,
+    };
+    (len > 0).then(|| (access, cpu.i..=cpu.i.saturating_add(len - 1)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Access, Breakpoint, Condition, Debugger, Register, Stop, Watchpoint};
+    use crate::machine::Machine;
+
+    // V0 += 1, call 0x206, loop back; the subroutine: V1 = 5, store V0-V1 at I, return
+    const ROM: [u8; 12] = [0x70, 0x01, 0x22, 0x06, 0x12, 0x00, 0x61, 0x05, 0xF1, 0x55, 0x00, 0xEE];
+
+    fn machine() -> Machine {
+        let mut machine = Machine::new();
+        machine.load_rom(&ROM).unwrap();
+        machine.cpu.i = 0x300;
+        machine
+    }
+
+    /// Runs frames until the debugger pauses
+    fn run(debugger: &mut Debugger, machine: &mut Machine) -> Stop {
+        loop {
+            if let Some(stop) = debugger.run_frame(machine, 0).unwrap() {
+                return stop;
+            }
+        }
+    }
+
+    #[test]
+    fn it_parses_conditions() {
+        let condition = Condition::parse("v3 >= 0x10").unwrap();
+        assert_eq!(condition.register, Register::V(3), "wrong register");
+        assert_eq!((condition.comparison, condition.value), (">=", 0x10), "wrong comparison");
+        assert_eq!(Condition::parse("dt==2").unwrap().register, Register::Dt, "spaces needed");
+        assert!(Condition::parse("vg == 1").is_err(), "unknown register accepted");
+        assert!(Condition::parse("v1 = 1").is_err(), "assignment accepted");
+    }
+
+    #[test]
+    fn it_stops_at_breakpoints() {
+        let mut machine = machine();
+        let mut debugger = Debugger::new();
+        debugger.breakpoints.push(Breakpoint { address: 0x200, condition: Condition::parse("v0 == 3").ok() });
+
+        debugger.resume_running();
+        assert!(matches!(run(&mut debugger, &mut machine), Stop::Breakpoint(0)), "breakpoint not hit");
+        assert_eq!((machine.cpu.pc, machine.cpu.v[0]), (0x200, 3), "condition not checked");
+        assert!(debugger.is_paused(), "still running");
+
+        debugger.step(1);
+        assert!(matches!(run(&mut debugger, &mut machine), Stop::Step), "resumed onto the breakpoint");
+        assert_eq!(machine.cpu.v[0], 4, "breakpoint not left");
+    }
+
+    #[test]
+    fn it_steps_over_and_out() {
+        let mut machine = machine();
+        let mut debugger = Debugger::new();
+
+        debugger.step(1);
+        run(&mut debugger, &mut machine);
+        debugger.step_over(&machine);
+        run(&mut debugger, &mut machine);
+        assert_eq!((machine.cpu.pc, machine.cpu.v[1]), (0x204, 5), "call not stepped over");
+
+        debugger.step(3);
+        run(&mut debugger, &mut machine);
+        assert_eq!(machine.cpu.pc, 0x206, "call not stepped into");
+        assert!(debugger.step_out(&machine), "not inside the call");
+        run(&mut debugger, &mut machine);
+        assert_eq!(machine.cpu.pc, 0x204, "call not stepped out of");
+        assert!(!debugger.step_out(&machine), "outside of a call");
+    }
+
+    #[test]
+    fn it_watches_memory() {
+        let mut machine = machine();
+        let mut debugger = Debugger::new();
+        debugger.watchpoints.push(Watchpoint { range: 0x301..=0x301, access: Access::Read });
+        debugger.watchpoints.push(Watchpoint { range: 0x301..=0x310, access: Access::Write });
+
+        debugger.resume_running();
+        let stop = run(&mut debugger, &mut machine);
+        assert!(matches!(stop, Stop::Watchpoint { index: 1, address: 0x301, access: Access::Write }), "write not watched");
+        assert_eq!(machine.cpu.pc, 0x208, "not stopped before the write");
+    }
+}
@@ -0,0 +1,312 @@
+//! Disassembler
+//!
+//! Uses the syntax of Cowgod's CHIP-8 reference, extended with the SUPER-CHIP
+//! and XO-CHIP instructions. Numbers are hexadecimal. Instructions are decoded
+//! by [`Instruction::decode`], same as the CPU does.
+//!
+//! [`disassemble`] follows the control flow from 0x200 to tell code from data:
+//! jumps, calls and both ways out of a skip are taken, returns and exits end a
+//! path. Whatever is never reached is listed as data. Jump and call targets
+//! get labels, and so do the addresses loaded into I.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::components::instruction::Instruction;
+use crate::components::platform::Platform;
+
+/// Where ROMs are loaded
+pub const START: u16 = 0x200;
+/// Data bytes per `DB` line
+const DATA_PER_LINE: usize = 8;
+
+/// Labels by address
+pub type Labels = BTreeMap<u16, String>;
+
+/// A line of a [`Listing`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Line {
+    pub address: u16,
+    pub bytes: Vec<u8>,
+    /// mnemonic of the instruction, or a `DB` directive for data
+    pub text: String,
+    /// reached by the control flow
+    pub code: bool,
+}
+
+/// Disassembly of a whole ROM
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Listing {
+    pub platform: Platform,
+    pub labels: Labels,
+    pub lines: Vec<Line>,
+}
+
+impl fmt::Display for Listing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for line in &self.lines {
+            if let Some(label) = self.labels.get(&line.address) {
+                writeln!(f, "{}:", label)?;
+            }
+            let bytes: String = match line.code {
+                true => line.bytes.iter().map(|byte| format!("{:02X}", byte)).collect(),
+                false => String::new(),
+            };
+            writeln!(f, "    {:#05X}  {:<8}  {}", line.address, bytes, line.text)?;
+        }
+        Ok(())
+    }
+}
+
+/// Disassembles a ROM as it is laid out from 0x200
+///
+/// Bytes past the end of the 64 KiB address space are left out.
+pub fn disassemble(rom: &[u8], platform: Platform) -> Listing {
+    let rom = &rom[..rom.len().min(Platform::XoChip.memory_size() - START as usize)];
+    let (code, labels) = trace(rom, platform);
+    let memory = image(rom);
+
+    let mut lines = vec![];
+    let mut offset = 0;
+    while offset < rom.len() {
+        let address = START + offset as u16;
+        if code[offset] {
+            let (text, len) = mnemonic(&memory, address as usize, platform, &labels);
+            lines.push(Line { address, bytes : rom[offset..offset + len].to_vec(), text, code : true });
+            offset += len;
+            continue;
+        }
+
+        // data runs up to the next code, label or full line
+        let mut end = offset + 1;
+        while end < rom.len() && end - offset < DATA_PER_LINE && !code[end] && !labels.contains_key(&(START + end as u16)) {
+            end += 1;
+        }
+        let bytes = rom[offset..end].to_vec();
+        let text: Vec<String> = bytes.iter().map(|byte| format!("{:#04X}", byte)).collect();
+        lines.push(Line { address, bytes, text : format!("DB {}", text.join(", ")), code : false });
+        offset = end;
+    }
+    Listing { platform, labels, lines }
+}
+
+/// Labels of the jump, call and I targets reachable from 0x200
+///
+/// Meant for memory as it is while running, `memory` starts at address 0.
+pub fn labels(memory: &[u8], platform: Platform) -> Labels {
+    trace(memory.get(START as usize..).unwrap_or_default(), platform).1
+}
+
+/// Mnemonic of the instruction at `address` with its size in bytes
+///
+/// XO-CHIP's `F000 NNNN` is 4 bytes long, everything else 2. Opcodes the platform
+/// does not define come out as `DW` data words. Addresses with a label are
+/// written as the label.
+pub fn mnemonic(memory: &[u8], address: usize, platform: Platform, labels: &Labels) -> (String, usize) {
+    let Some(opcode) = word(memory, address) else {
+        return (format!("DB {:#04X}", memory.get(address).copied().unwrap_or_default()), 1);
+    };
+    let Some(instruction) = Instruction::decode(opcode, platform) else {
+        return (format!("DW {:#06X}", opcode), 2);
+    };
+    let name = |address: u16, digits: usize| match labels.get(&address) {
+        Some(label) => label.clone(),
+        None => format!("{:#0width$X}", address, width = digits + 2),
+    };
+
+    let text = match instruction {
+        Instruction::Cls => "CLS".to_string(),
+        Instruction::Ret => "RET".to_string(),
+        Instruction::ScrollDown(n) => format!("SCD {:#X}", n),
+        Instruction::ScrollUp(n) => format!("SCU {:#X}", n),
+        Instruction::ScrollRight => "SCR".to_string(),
+        Instruction::ScrollLeft => "SCL".to_string(),
+        Instruction::Exit => "EXIT".to_string(),
+        Instruction::Lores => "LOW".to_string(),
+        Instruction::Hires => "HIGH".to_string(),
+        Instruction::Jump(nnn) => format!("JP {}", name(nnn, 3)),
+        Instruction::Call(nnn) => format!("CALL {}", name(nnn, 3)),
+        Instruction::SkipEqByte { x, kk } => format!("SE V{:X}, {:#04X}", x, kk),
+        Instruction::SkipNeByte { x, kk } => format!("SNE V{:X}, {:#04X}", x, kk),
+        Instruction::SkipEq { x, y } => format!("SE V{:X}, V{:X}", x, y),
+        Instruction::SaveRange { x, y } => format!("SAVE V{:X}-V{:X}", x, y),
+        Instruction::LoadRange { x, y } => format!("LOAD V{:X}-V{:X}", x, y),
+        Instruction::LoadByte { x, kk } => format!("LD V{:X}, {:#04X}", x, kk),
+        Instruction::AddByte { x, kk } => format!("ADD V{:X}, {:#04X}", x, kk),
+        Instruction::Load { x, y } => format!("LD V{:X}, V{:X}", x, y),
+        Instruction::Or { x, y } => format!("OR V{:X}, V{:X}", x, y),
+        Instruction::And { x, y } => format!("AND V{:X}, V{:X}", x, y),
+        Instruction::Xor { x, y } => format!("XOR V{:X}, V{:X}", x, y),
+        Instruction::Add { x, y } => format!("ADD V{:X}, V{:X}", x, y),
+        Instruction::Sub { x, y } => format!("SUB V{:X}, V{:X}", x, y),
+        Instruction::Shr { x, y } => format!("SHR V{:X}, V{:X}", x, y),
+        Instruction::SubN { x, y } => format!("SUBN V{:X}, V{:X}", x, y),
+        Instruction::Shl { x, y } => format!("SHL V{:X}, V{:X}", x, y),
+        Instruction::SkipNe { x, y } => format!("SNE V{:X}, V{:X}", x, y),
+        Instruction::LoadI(nnn) => format!("LD I, {}", name(nnn, 3)),
+        Instruction::JumpOffset { nnn, .. } => format!("JP V0, {}", name(nnn, 3)),
+        Instruction::Random { x, kk } => format!("RND V{:X}, {:#04X}", x, kk),
+        Instruction::Draw { x, y, n } => format!("DRW V{:X}, V{:X}, {:#X}", x, y, n),
+        Instruction::SkipKey(x) => format!("SKP V{:X}", x),
+        Instruction::SkipNotKey(x) => format!("SKNP V{:X}", x),
+        Instruction::LoadLongI => match word(memory, address + 2) {
+            Some(long) => return (format!("LD I, {}", name(long, 4)), 4),
+            None => format!("DW {:#06X}", opcode),
+        },
+        Instruction::Plane(planes) => format!("PLANE {:#X}", planes),
+        Instruction::Audio => "AUDIO".to_string(),
+        Instruction::GetDelay(x) => format!("LD V{:X}, DT", x),
+        Instruction::WaitKey(x) => format!("LD V{:X}, K", x),
+        Instruction::SetDelay(x) => format!("LD DT, V{:X}", x),
+        Instruction::SetSound(x) => format!("LD ST, V{:X}", x),
+        Instruction::AddI(x) => format!("ADD I, V{:X}", x),
+        Instruction::Font(x) => format!("LD F, V{:X}", x),
+        Instruction::BigFont(x) => format!("LD HF, V{:X}", x),
+        Instruction::Bcd(x) => format!("LD B, V{:X}", x),
+        Instruction::Pitch(x) => format!("PITCH V{:X}", x),
+        Instruction::Store(x) => format!("LD [I], V{:X}", x),
+        Instruction::Read(x) => format!("LD V{:X}, [I]", x),
+        Instruction::SaveFlags(x) => format!("LD R, V{:X}", x),
+        Instruction::LoadFlags(x) => format!("LD V{:X}, R", x),
+    };
+    (text, 2)
+}
+
+/// Big-endian word at `address`
+fn word(memory: &[u8], address: usize) -> Option<u16> {
+    Some((*memory.get(address)? as u16) << 8 | *memory.get(address + 1)? as u16)
+}
+
+/// The ROM at its address, so mnemonics can read it like memory
+fn image(rom: &[u8]) -> Vec<u8> {
+    let mut memory = vec![0; START as usize];
+    memory.extend_from_slice(rom);
+    memory
+}
+
+/// Which bytes of the ROM are code, and the labels of the targets
+fn trace(rom: &[u8], platform: Platform) -> (Vec<bool>, Labels) {
+    let memory = image(rom);
+    let in_rom = |address: u16| (START as usize..memory.len()).contains(&(address as usize));
+    let decode = |address: u16| {
+        let instruction = Instruction::decode(word(&memory, address as usize)?, platform)?;
+        (address as usize + instruction.size() <= memory.len()).then_some(instruction)
+    };
+
+    let mut code = vec![false; rom.len()];
+    let mut calls = vec![];
+    let mut jumps = vec![];
+    let mut data = vec![];
+    let mut pending = vec![START];
+
+    while let Some(address) = pending.pop() {
+        if !in_rom(address) {
+            continue;
+        }
+        let Some(instruction) = decode(address) else {
+            continue;
+        };
+        let range = (address - START) as usize..(address - START) as usize + instruction.size();
+        // already traced, or overlapping an instruction that was
+        if code[range.clone()].iter().any(|&byte| byte) {
+            continue;
+        }
+        code[range].fill(true);
+
+        let next = address.wrapping_add(instruction.size() as u16);
+        match instruction {
+            Instruction::Ret | Instruction::Exit => (),
+            Instruction::Jump(target) => {
+                jumps.push(target);
+                pending.push(target);
+            }
+            Instruction::Call(target) => {
+                calls.push(target);
+                pending.extend([next, target]);
+            }
+            // the offset is only known at runtime, the table itself usually holds jumps
+            Instruction::JumpOffset { nnn, .. } => {
+                jumps.push(nnn);
+                pending.push(nnn);
+            }
+            instruction if instruction.is_skip() => {
+                let skipped = decode(next).map_or(2, |instruction| instruction.size());
+                pending.extend([next.wrapping_add(skipped as u16), next]);
+            }
+            Instruction::LoadI(target) => {
+                data.push(target);
+                pending.push(next);
+            }
+            Instruction::LoadLongI => {
+                data.extend(word(&memory, address as usize + 2));
+                pending.push(next);
+            }
+            _ => pending.push(next),
+        }
+    }
+
+    // calls win over jumps, and both over I targets
+    let mut labels = Labels::new();
+    for (targets, prefix) in [(data, "data"), (jumps, "label"), (calls, "sub")] {
+        for target in targets.into_iter().filter(|&target| in_rom(target)) {
+            labels.insert(target, format!("{}_{:03X}", prefix, target));
+        }
+    }
+    (code, labels)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{disassemble, mnemonic, Labels};
+    use crate::components::platform::Platform;
+
+    #[test]
+    fn it_names_instructions() {
+        let memory = [0x00, 0xE0, 0xD1, 0x25, 0xF0, 0x00, 0x12, 0x34, 0x50, 0x12];
+        let labels = Labels::from([(0x1234, "data_1234".to_string())]);
+
+        assert_eq!(mnemonic(&memory, 0, Platform::Chip8, &labels), ("CLS".to_string(), 2));
+        assert_eq!(mnemonic(&memory, 2, Platform::Chip8, &labels), ("DRW V1, V2, 0x5".to_string(), 2));
+        assert_eq!(mnemonic(&memory, 4, Platform::XoChip, &labels), ("LD I, data_1234".to_string(), 4), "long load not read");
+        assert_eq!(mnemonic(&memory, 4, Platform::Chip8, &labels), ("DW 0xF000".to_string(), 2), "XO-CHIP opcode on CHIP-8");
+        assert_eq!(mnemonic(&memory, 6, Platform::Chip8, &Labels::new()), ("JP 0x234".to_string(), 2));
+        assert_eq!(mnemonic(&memory, 8, Platform::XoChip, &labels), ("SAVE V0-V1".to_string(), 2));
+        assert_eq!(mnemonic(&memory, 9, Platform::Chip8, &labels), ("DB 0x12".to_string(), 1), "odd byte at the end");
+    }
+
+    #[test]
+    fn it_separates_code_from_data() {
+        // I = sprite, call draw, skip the jump when V0 = 0, exit; draw: DRW, RET; sprite
+        let rom = [0xA2, 0x0E, 0x22, 0x0A, 0x30, 0x00, 0x12, 0x00, 0x00, 0xFD, 0xD0, 0x01, 0x00, 0xEE, 0xF0, 0x90];
+        let listing = disassemble(&rom, Platform::SuperChip);
+
+        let text: Vec<&str> = listing.lines.iter().map(|line| line.text.as_str()).collect();
+        assert_eq!(text, [
+            "LD I, data_20E", "CALL sub_20A", "SE V0, 0x00", "JP label_200", "EXIT",
+            "DRW V0, V0, 0x1", "RET", "DB 0xF0, 0x90",
+        ]);
+        assert!(listing.lines[..7].iter().all(|line| line.code), "code taken for data");
+        assert!(!listing.lines[7].code, "data taken for code");
+        assert_eq!(listing.labels.len(), 3, "wrong labels");
+        assert!(listing.to_string().starts_with("label_200:\n    0x200  A20E      LD I, data_20E\n"), "labels not listed");
+    }
+
+    #[test]
+    fn it_skips_long_loads() {
+        // skip over F000 NNNN on XO-CHIP, then loop
+        let rom = [0x30, 0x00, 0xF0, 0x00, 0x02, 0x08, 0x12, 0x06, 0xAA];
+        let listing = disassemble(&rom, Platform::XoChip);
+
+        assert_eq!(listing.lines.len(), 4, "long load misread");
+        assert_eq!(listing.lines[1].text, "LD I, data_208");
+        assert_eq!(listing.lines[2].text, "JP label_206");
+        assert_eq!(listing.lines[3].text, "DB 0xAA");
+    }
+
+    #[test]
+    fn it_stops_at_the_end_of_memory() {
+        let listing = disassemble(&vec![0xAA; 0x10000], Platform::XoChip);
+        let last = listing.lines.last().unwrap();
+        assert_eq!(last.address as usize + last.bytes.len(), 0x10000, "listed past the end of memory");
+    }
+}
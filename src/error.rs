@@ -0,0 +1,101 @@
+use std::{error, fmt, io};
+
+/// Everything that can stop the emulator
+///
+/// Errors raised by an instruction carry its address (`pc`) and `opcode`.
+#[derive(Debug)]
+pub enum Chip8Error {
+    /// the ROM does not fit in memory after 0x200
+    RomTooLarge { size: usize, max: usize },
+    /// the next instruction lies outside of memory
+    PcOutOfBounds { pc: u16 },
+    /// the instruction accesses memory past its end
+    MemoryOutOfBounds { pc: u16, opcode: u16, address: usize },
+    /// 2NNN with a full call stack
+    StackOverflow { pc: u16, opcode: u16 },
+    /// 00EE with an empty call stack
+    StackUnderflow { pc: u16, opcode: u16 },
+    UnknownOpcode { pc: u16, opcode: u16 },
+    /// the save state cannot be loaded, the reason says why
+    InvalidState(String),
+    /// the movie cannot be played back, the reason says why
+    InvalidMovie(String),
+    /// the frame numbered `frame`, counted from 0, ended differently than in the movie
+    Desync { frame: u64 },
+    /// a source or symbol file is malformed at `line`, counted from 1
+    Syntax { line: usize, message: String },
+    Io(io::Error),
+}
+
+impl Chip8Error {
+    /// Address of the offending instruction
+    pub fn pc(&self) -> Option<u16> {
+        match self {
+            Chip8Error::PcOutOfBounds { pc }
+            | Chip8Error::MemoryOutOfBounds { pc, .. }
+            | Chip8Error::StackOverflow { pc, .. }
+            | Chip8Error::StackUnderflow { pc, .. }
+            | Chip8Error::UnknownOpcode { pc, .. } => Some(*pc),
+            Chip8Error::RomTooLarge { .. }
+            | Chip8Error::InvalidState(_)
+            | Chip8Error::InvalidMovie(_)
+            | Chip8Error::Desync { .. }
+            | Chip8Error::Syntax { .. }
+            | Chip8Error::Io(_) => None,
+        }
+    }
+
+    /// The offending instruction
+    pub fn opcode(&self) -> Option<u16> {
+        match self {
+            Chip8Error::MemoryOutOfBounds { opcode, .. }
+            | Chip8Error::StackOverflow { opcode, .. }
+            | Chip8Error::StackUnderflow { opcode, .. }
+            | Chip8Error::UnknownOpcode { opcode, .. } => Some(*opcode),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Chip8Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Chip8Error::RomTooLarge { size, max } =>
+                write!(f, "the ROM is {} bytes, only {} fit in memory", size, max),
+            Chip8Error::PcOutOfBounds { pc } =>
+                write!(f, "pc {:#05X} is outside of memory", pc),
+            Chip8Error::MemoryOutOfBounds { pc, opcode, address } =>
+                write!(f, "{:04X} at {:#05X} accesses memory past its end ({:#X})", opcode, pc, address),
+            Chip8Error::StackOverflow { pc, opcode } =>
+                write!(f, "{:04X} at {:#05X} overflows the call stack", opcode, pc),
+            Chip8Error::StackUnderflow { pc, opcode } =>
+                write!(f, "{:04X} at {:#05X} returns with an empty call stack", opcode, pc),
+            Chip8Error::UnknownOpcode { pc, opcode } =>
+                write!(f, "unknown opcode {:04X} at {:#05X}", opcode, pc),
+            Chip8Error::InvalidState(reason) =>
+                write!(f, "the save state cannot be loaded: {}", reason),
+            Chip8Error::InvalidMovie(reason) =>
+                write!(f, "the movie cannot be played: {}", reason),
+            Chip8Error::Desync { frame } =>
+                write!(f, "the emulation desynced from the movie at frame {}", frame),
+            Chip8Error::Syntax { line, message } =>
+                write!(f, "line {}: {}", line, message),
+            Chip8Error::Io(error) => error.fmt(f),
+        }
+    }
+}
+
+impl error::Error for Chip8Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Chip8Error::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Chip8Error {
+    fn from(error: io::Error) -> Self {
+        Chip8Error::Io(error)
+    }
+}
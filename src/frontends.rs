@@ -0,0 +1,163 @@
+pub mod headless;
+pub mod repl;
+#[cfg(feature = "audio-device")]
+pub mod speaker;
+pub mod terminal;
+pub mod window;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use chip8_rs::{Chip8Error, Machine};
+use chip8_rs::clock::Clock;
+use chip8_rs::components::{input::Keymap, platform::Platform};
+use chip8_rs::config::WindowConfig;
+use chip8_rs::machine::{ReloadMode, FRAME_RATE};
+use chip8_rs::state::{StateStore, SLOTS};
+use chip8_rs::symbols::Symbols;
+
+/// Switches shared by the interactive frontends
+#[derive(Clone, Default)]
+pub struct Options {
+    /// keyboard bindings of the hex keys
+    pub keymap: Keymap,
+    pub window: WindowConfig,
+    /// start paused in the debugger
+    pub debug: bool,
+    /// draw on the window with the mouse
+    pub draw: bool,
+    /// run frames back to back instead of at 60 Hz
+    pub fast: bool,
+    /// names and breakpoints for the debugger
+    pub symbols: Symbols,
+    /// file to reload the ROM from when it changes
+    pub watch: Option<Watch>,
+}
+
+/// Turns a watched file into a ROM, and into a symbol map when it is source
+pub type Build = fn(&Path, Platform) -> Result<(Vec<u8>, Option<Symbols>), String>;
+
+/// Frames between two looks at the watched file
+const WATCH_INTERVAL: u32 = 15;
+
+/// Reloads the ROM when the file it came from changes
+///
+/// Editors often write a file in several goes, so a change is only acted on once
+/// the modification time has stayed the same for one more look.
+#[derive(Clone)]
+pub struct Watch {
+    /// the ROM, or the source it is assembled from
+    pub path: PathBuf,
+    pub mode: ReloadMode,
+    /// platform the source is assembled for
+    pub platform: Platform,
+    /// turns the file into a ROM, and the symbol map of a source
+    pub build: Build,
+    modified: Option<SystemTime>,
+    pending: bool,
+    countdown: u32,
+}
+
+impl Watch {
+    pub fn new(path: PathBuf, mode: ReloadMode, platform: Platform, build: Build) -> Self {
+        let modified = Self::modified(&path);
+        Watch { path, mode, platform, build, modified, pending : false, countdown : WATCH_INTERVAL }
+    }
+
+    fn modified(path: &Path) -> Option<SystemTime> {
+        fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+    }
+
+    /// Called once per frame, reloads the ROM if the file has changed and says how that went
+    ///
+    /// A reloaded ROM has a new hash, so `states` moves to the store of the new
+    /// one, and a rebuilt source replaces `symbols`.
+    pub fn poll(&mut self, machine: &mut Machine, states: &mut Option<StateStore>, symbols: &mut Symbols) -> Option<String> {
+        if self.countdown > 0 {
+            self.countdown -= 1;
+            return None;
+        }
+        self.countdown = WATCH_INTERVAL;
+
+        let modified = Self::modified(&self.path);
+        if modified != self.modified {
+            self.modified = modified;
+            self.pending = modified.is_some();
+            return None;
+        }
+        if !self.pending {
+            return None;
+        }
+        self.pending = false;
+
+        let name = self.path.file_name().unwrap_or_default().to_string_lossy();
+        let result = (self.build)(&self.path, self.platform)
+            .and_then(|(rom, built)| machine.reload_rom(&rom, self.mode).map(|()| built).map_err(|error| error.to_string()));
+        Some(match result {
+            Ok(built) => {
+                if states.is_some() {
+                    *states = StateStore::for_rom(machine.rom_hash());
+                }
+                if let Some(built) = built {
+                    *symbols = built;
+                }
+                format!("Reloaded {} at frame {}", name, machine.frames)
+            }
+            Err(error) => format!("Cannot reload {}: {}", name, error),
+        })
+    }
+}
+
+/// Actions bound to keys outside of the keypad
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Hotkey {
+    /// Shift+F1 to Shift+F9
+    SaveState(u8),
+    /// F1 to F9
+    LoadState(u8),
+}
+
+impl Hotkey {
+    /// Hotkey of the function key `number`
+    pub fn function_key(number: u8, shift: bool) -> Option<Self> {
+        match number {
+            1..=SLOTS if shift => Some(Hotkey::SaveState(number)),
+            1..=SLOTS => Some(Hotkey::LoadState(number)),
+            _ => None,
+        }
+    }
+}
+
+/// Carries the hotkey out, returns what to tell the user
+pub fn run_hotkey(machine: &mut Machine, states: Option<&StateStore>, hotkey: Hotkey) -> String {
+    let Some(states) = states else {
+        return "Save states are not available".to_string();
+    };
+
+    match hotkey {
+        Hotkey::SaveState(slot) => match states.save(slot, machine) {
+            Ok(()) => format!("Saved to slot {}", slot),
+            Err(error) => format!("Cannot save to slot {}: {}", slot, error),
+        },
+        Hotkey::LoadState(slot) => match states.load(slot, machine) {
+            Ok(()) => format!("Loaded slot {}", slot),
+            Err(Chip8Error::Io(_)) => format!("Slot {} is empty", slot),
+            Err(error) => format!("Cannot load slot {}: {}", slot, error),
+        },
+    }
+}
+
+/// Calls `frame` at 60 Hz until it returns `false` or an error
+///
+/// `fast` drops the pacing and runs frames back to back.
+pub fn run_paced(fast: bool, mut frame: impl FnMut() -> Result<bool, Chip8Error>) -> Result<(), Chip8Error> {
+    let mut clock = Clock::new(FRAME_RATE);
+
+    while frame()? {
+        if !fast {
+            clock.wait();
+        }
+    }
+    Ok(())
+}
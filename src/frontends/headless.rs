@@ -0,0 +1,70 @@
+use std::time::{Duration, Instant};
+
+use chip8_rs::{Chip8Error, Machine};
+use chip8_rs::components::input::{InputSource, NullInput};
+
+/// When a headless run ends
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Limits {
+    /// frames to run for, `None` for no limit
+    pub frames: Option<u64>,
+    /// instructions to run for, `None` for no limit
+    pub instructions: Option<u64>,
+    /// stop once the program halts, see [`Machine::is_halted`]
+    pub until_halt: bool,
+    /// wall-clock time after which the run fails
+    pub timeout: Option<Duration>,
+    /// instructions after which the run fails
+    pub max_instructions: Option<u64>,
+}
+
+/// How a headless run ended
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    /// ran as long as it was asked to
+    Finished,
+    /// the program exited, or halted under [`Limits::until_halt`]
+    Halted,
+    /// the time or instruction budget ran out first
+    OutOfBudget(&'static str),
+}
+
+/// Runs as fast as possible until one of the `limits` is reached or the program exits
+///
+/// The input is polled at the start of every frame.
+pub fn run(machine: &mut Machine, input: Option<Box<dyn InputSource>>, limits: Limits) -> Result<Outcome, Chip8Error> {
+    let mut input = input.unwrap_or(Box::new(NullInput));
+    let started = Instant::now();
+    let mut frame = None;
+
+    loop {
+        let instructions = machine.stats.instructions;
+        if machine.cpu.exited || (limits.until_halt && machine.is_halted()) {
+            return Ok(Outcome::Halted);
+        }
+        if limits.frames.is_some_and(|frames| machine.frames >= frames)
+            || limits.instructions.is_some_and(|limit| instructions >= limit) {
+            return Ok(Outcome::Finished);
+        }
+        if limits.max_instructions.is_some_and(|budget| instructions >= budget) {
+            return Ok(Outcome::OutOfBudget("instruction"));
+        }
+
+        if frame != Some(machine.frames) {
+            frame = Some(machine.frames);
+            if limits.timeout.is_some_and(|timeout| started.elapsed() >= timeout) {
+                return Ok(Outcome::OutOfBudget("time"));
+            }
+            machine.set_keys(input.poll());
+        }
+        machine.step()?;
+
+        for warning in machine.warnings.drain(..) {
+            eprintln!("Warning: {}", warning);
+        }
+        // there is no debugger to break into
+        if let Some(trap) = machine.trap.take() {
+            return Err(trap);
+        }
+    }
+}
@@ -0,0 +1,278 @@
+use std::io::{self, Write};
+
+use chip8_rs::{Chip8Error, Machine};
+use chip8_rs::debugger::{parse_number, Access, Breakpoint, Condition, Debugger, Stop, Watchpoint};
+use chip8_rs::disasm;
+use chip8_rs::symbols::Symbols;
+
+use super::Options;
+
+const HELP: &str = "\
+s, step [N]              run N instructions (1)
+n, next                  step, running a 2NNN call through to its return
+o, out                   run until the current subroutine returns
+c, continue              run until a breakpoint, watchpoint or break
+back                     undo the last instruction
+b, break ADDR [if COND]  stop at ADDR, e.g. `b 0x2A4 if v3 == 0x10` or `b draw`
+w, watch ADDR[-END] [r|w|rw]
+                         stop before an instruction reads or writes memory
+l, list                  list breakpoints and watchpoints
+delete N, unwatch N      remove breakpoint or watchpoint N
+r, regs                  registers
+stack                    call stack
+dis [ADDR] [COUNT]       disassembly (from the pc)
+x, mem ADDR [LEN]        memory dump
+q, quit                  stop the emulation
+Numbers are decimal, or hexadecimal with 0x. Addresses may also be labels of the symbol map.
+An empty line repeats the last command.";
+
+/// Debugger driven by commands typed into the terminal
+#[derive(Default)]
+pub struct Repl {
+    pub debugger: Debugger,
+    /// why the debugger paused, shown by the next prompt
+    pub stop: Option<Stop>,
+    /// names from `--symbols` or the assembler
+    pub symbols: Symbols,
+    /// repeated on an empty line
+    last: String,
+}
+
+impl Repl {
+    /// A paused debugger
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A paused debugger knowing the names of `symbols` and stopping at its breakpoints
+    pub fn with_symbols(symbols: Symbols) -> Self {
+        let mut repl = Repl::new();
+        repl.set_symbols(symbols);
+        repl
+    }
+
+    /// Swaps the symbol map, the breakpoints of the old one for those of `symbols`
+    ///
+    /// Breakpoints added in the debugger stay.
+    pub fn set_symbols(&mut self, symbols: Symbols) {
+        let old: Vec<u16> = self.symbols.breakpoints.iter().map(|(_, address)| *address).collect();
+        self.debugger.breakpoints.retain(|breakpoint| breakpoint.condition.is_some() || !old.contains(&breakpoint.address));
+        for (_, address) in &symbols.breakpoints {
+            self.debugger.breakpoints.push(Breakpoint { address : *address, condition : None });
+        }
+        self.symbols = symbols;
+    }
+
+    /// The debugger a frontend starts with: paused with `--debug`, running
+    /// when there are only breakpoints of the symbol map, none otherwise
+    pub fn for_options(options: &Options) -> Option<Self> {
+        if !options.debug && options.symbols.breakpoints.is_empty() {
+            return None;
+        }
+        let mut repl = Repl::with_symbols(options.symbols.clone());
+        if !options.debug {
+            repl.debugger.resume_running();
+        }
+        Some(repl)
+    }
+
+    /// Lets the debugger run the rest of the frame, see [`Debugger::run_frame`]
+    pub fn run_frame(&mut self, machine: &mut Machine, keys: u16) -> Result<(), Chip8Error> {
+        if let Some(stop) = self.debugger.run_frame(machine, keys)? {
+            self.stop = Some(stop);
+        }
+        Ok(())
+    }
+
+    /// Reads commands until one resumes the machine, `false` when asked to quit
+    pub fn prompt(&mut self, machine: &mut Machine) -> bool {
+        if let Some(stop) = self.stop.take() {
+            self.describe(&stop);
+        }
+        println!("{}", self.disassembly(machine, machine.cpu.pc, 1));
+
+        loop {
+            print!("(chip8) ");
+            let _ = io::stdout().flush();
+
+            let mut line = String::new();
+            match io::stdin().read_line(&mut line) {
+                Ok(0) | Err(_) => return false,
+                Ok(_) => (),
+            }
+            let line = match line.trim() {
+                "" => self.last.clone(),
+                line => line.to_string(),
+            };
+            self.last = line.clone();
+
+            match self.execute(&line, machine) {
+                Ok(Some(resumed)) => return resumed,
+                Ok(None) => (),
+                Err(error) => println!("{}", error),
+            }
+        }
+    }
+
+    /// `Some(true)` when the command resumed the machine, `Some(false)` to quit
+    fn execute(&mut self, line: &str, machine: &mut Machine) -> Result<Option<bool>, String> {
+        let mut words = line.split_whitespace();
+        let command = words.next().unwrap_or_default();
+        let arguments: Vec<&str> = words.collect();
+        let number = |index: usize| arguments.get(index).map(|argument| parse_number(argument)).transpose();
+        let address = |index: usize| arguments.get(index).map(|argument| self.address(argument)).transpose();
+
+        match command {
+            "s" | "step" => self.debugger.step(number(0)?.unwrap_or(1) as u32),
+            "n" | "next" => self.debugger.step_over(machine),
+            "o" | "out" => if !self.debugger.step_out(machine) {
+                return Err("Not inside a subroutine".to_string());
+            },
+            "c" | "continue" => self.debugger.resume_running(),
+            "back" => {
+                match machine.step_back() {
+                    Ok(true) => println!("{}", self.disassembly(machine, machine.cpu.pc, 1)),
+                    Ok(false) => println!("Nothing to undo, rewinding is off or the buffer ran out"),
+                    Err(error) => println!("{}", error),
+                }
+                return Ok(None);
+            }
+            "b" | "break" => {
+                let address = address(0)?.ok_or("Break where?")?;
+                let condition = match arguments.get(1) {
+                    Some(&"if") => Some(Condition::parse(&arguments[2..].join(" "))?),
+                    Some(_) => return Err("Conditions start with `if`".to_string()),
+                    None => None,
+                };
+                self.debugger.breakpoints.push(Breakpoint { address, condition });
+                println!("Breakpoint {} at {:#05X}", self.debugger.breakpoints.len() - 1, address);
+                return Ok(None);
+            }
+            "w" | "watch" => {
+                let range = arguments.first().ok_or("Watch what?")?;
+                let (start, end) = match range.split_once('-') {
+                    Some((start, end)) => (self.address(start)?, self.address(end)?),
+                    None => (self.address(range)?, self.address(range)?),
+                };
+                let access = match arguments.get(1) {
+                    Some(name) => Access::from_name(name).ok_or(format!("\"{}\" is not r, w or rw", name))?,
+                    None => Access::ReadWrite,
+                };
+                self.debugger.watchpoints.push(Watchpoint { range: start.min(end)..=start.max(end), access });
+                println!("Watchpoint {} on {:#05X}-{:#05X}", self.debugger.watchpoints.len() - 1, start.min(end), start.max(end));
+                return Ok(None);
+            }
+            "l" | "list" => {
+                for (index, breakpoint) in self.debugger.breakpoints.iter().enumerate() {
+                    match breakpoint.condition {
+                        Some(condition) => println!("breakpoint {}: {:#05X} if {}", index, breakpoint.address, condition),
+                        None => println!("breakpoint {}: {:#05X}", index, breakpoint.address),
+                    }
+                }
+                for (index, watchpoint) in self.debugger.watchpoints.iter().enumerate() {
+                    println!("watchpoint {}: {:#05X}-{:#05X} {:?}", index, watchpoint.range.start(), watchpoint.range.end(), watchpoint.access);
+                }
+                return Ok(None);
+            }
+            "delete" | "unwatch" => {
+                let index = number(0)?.ok_or("Which one?")? as usize;
+                let removed = match command {
+                    "delete" => (index < self.debugger.breakpoints.len()).then(|| self.debugger.breakpoints.remove(index)).is_some(),
+                    _ => (index < self.debugger.watchpoints.len()).then(|| self.debugger.watchpoints.remove(index)).is_some(),
+                };
+                if !removed {
+                    return Err(format!("There is no number {}", index));
+                }
+                return Ok(None);
+            }
+            "r" | "regs" => {
+                let cpu = &machine.cpu;
+                let registers: Vec<String> = cpu.v.iter().enumerate().map(|(index, v)| format!("V{:X}={:02X}", index, v)).collect();
+                println!("{}", registers.join(" "));
+                println!("I={:#05X} PC={:#05X} DT={} ST={}", cpu.i, cpu.pc, cpu.dt, cpu.st);
+                return Ok(None);
+            }
+            "stack" => {
+                let stack = &machine.cpu.stack;
+                let entries: Vec<String> = stack.entries().iter().map(|address| format!("{:#05X}", address)).collect();
+                println!("{}/{} [{}]", stack.len(), stack.depth(), entries.join(", "));
+                return Ok(None);
+            }
+            "dis" => {
+                let address = address(0)?.unwrap_or(machine.cpu.pc);
+                let count = number(1)?.unwrap_or(10) as usize;
+                println!("{}", self.disassembly(machine, address, count));
+                return Ok(None);
+            }
+            "x" | "mem" => {
+                let address = address(0)?.ok_or("Dump what?")? as usize;
+                let len = number(1)?.unwrap_or(16) as usize;
+                let memory = &machine.cpu.memory;
+                let end = (address + len).min(memory.len());
+                for start in (address.min(end)..end).step_by(16) {
+                    let bytes: Vec<String> = memory[start..(start + 16).min(end)].iter().map(|byte| format!("{:02X}", byte)).collect();
+                    println!("{:#05X}  {}", start, bytes.join(" "));
+                }
+                return Ok(None);
+            }
+            "q" | "quit" => return Ok(Some(false)),
+            "h" | "help" => {
+                println!("{}", HELP);
+                return Ok(None);
+            }
+            _ => return Err(format!("Unknown command \"{}\", try help", command)),
+        }
+        Ok(Some(true))
+    }
+
+    /// A number or the address of a label
+    fn address(&self, text: &str) -> Result<u16, String> {
+        match self.symbols.address(text) {
+            Some(address) => Ok(address),
+            None => parse_number(text),
+        }
+    }
+
+    fn describe(&self, stop: &Stop) {
+        match stop {
+            Stop::Step => (),
+            Stop::Breakpoint(index) => {
+                let address = self.debugger.breakpoints[*index].address;
+                match self.symbols.breakpoints.iter().find(|(_, breakpoint)| *breakpoint == address) {
+                    Some((name, _)) => println!("Breakpoint {} ({})", index, name),
+                    None => println!("Breakpoint {}", index),
+                }
+            }
+            Stop::Watchpoint { index, address, access } =>
+                println!("Watchpoint {}: {:?} at {:#05X}", index, access, address),
+            Stop::Trap(error) => println!("Break: {}", error),
+            Stop::Exited => println!("The program has exited"),
+        }
+    }
+
+    /// `count` instructions from `address`, marking the pc and the breakpoints
+    fn disassembly(&self, machine: &Machine, address: u16, count: usize) -> String {
+        let cpu = &machine.cpu;
+        // traced again every time, the program may have changed itself
+        let mut labels = disasm::labels(&cpu.memory, cpu.platform);
+        labels.extend(self.symbols.by_address());
+        let mut lines = vec![];
+        let mut address = address as usize;
+
+        for _ in 0..count {
+            if address >= cpu.memory.len() {
+                break;
+            }
+            if let Some(label) = labels.get(&(address as u16)) {
+                lines.push(format!("{}:", label));
+            }
+            let (text, len) = disasm::mnemonic(&cpu.memory, address, cpu.platform, &labels);
+            let bytes: String = cpu.memory[address..address + len].iter().map(|byte| format!("{:02X}", byte)).collect();
+            let pc = if address == cpu.pc as usize { "=>" } else { "  " };
+            let breakpoint = if self.debugger.breakpoints.iter().any(|breakpoint| breakpoint.address as usize == address) { "*" } else { " " };
+            lines.push(format!("{}{} {:#05X}  {:<8}  {}", pc, breakpoint, address, bytes, text));
+            address += len;
+        }
+        lines.join("\n")
+    }
+}
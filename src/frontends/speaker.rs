@@ -0,0 +1,56 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use chip8_rs::components::audio::AudioSink;
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+
+/// Plays the rendered audio on the default output device
+pub struct SpeakerSink {
+    /// dropping the stream stops the playback
+    _stream: cpal::Stream,
+    buffer: Arc<Mutex<VecDeque<f32>>>,
+    pub sample_rate: u32,
+}
+
+impl SpeakerSink {
+    /// `None` when there is no usable output device
+    pub fn open() -> Option<Self> {
+        let device = cpal::default_host().default_output_device()?;
+        let config = device.default_output_config().ok()?;
+        let sample_rate = config.sample_rate().0;
+        let channels = config.channels() as usize;
+
+        let buffer = Arc::new(Mutex::new(VecDeque::new()));
+        let callback_buffer = Arc::clone(&buffer);
+
+        let stream = device.build_output_stream(
+            &config.into(),
+            move |data: &mut [f32], _: &cpal::OutputCallbackInfo| {
+                let mut buffer = callback_buffer.lock().unwrap();
+                for frame in data.chunks_mut(channels) {
+                    let sample = buffer.pop_front().unwrap_or(0.0);
+                    frame.fill(sample);
+                }
+            },
+            |error| eprintln!("Audio output error: {}", error),
+            None,
+        ).ok()?;
+        stream.play().ok()?;
+
+        Some(SpeakerSink { _stream: stream, buffer, sample_rate })
+    }
+}
+
+impl AudioSink for SpeakerSink {
+    fn write(&mut self, samples: &[f32]) {
+        let mut buffer = self.buffer.lock().unwrap();
+        buffer.extend(samples);
+
+        // never lag more than a quarter of a second behind the emulation
+        let limit = self.sample_rate as usize / 4;
+        if buffer.len() > limit {
+            let excess = buffer.len() - limit;
+            buffer.drain(..excess);
+        }
+    }
+}
@@ -0,0 +1,206 @@
+use std::io::{self, Write};
+use std::time::Duration;
+
+use chip8_rs::{Display, Machine};
+use chip8_rs::components::audio::AudioSink;
+use chip8_rs::Chip8Error;
+use chip8_rs::components::input::{InputSource, Keymap};
+use crossterm::cursor::MoveTo;
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyModifiers};
+use crossterm::style::Print;
+use crossterm::terminal::{self, Clear, ClearType};
+
+use chip8_rs::state::StateStore;
+
+use chip8_rs::debugger::Stop;
+
+use super::repl::Repl;
+use super::{Hotkey, Options};
+
+/// Keypad fed from crossterm key events
+///
+/// Terminals only report presses, so a key counts as held for the frame it arrived in.
+pub struct CrosstermInput {
+    keymap: Keymap,
+    /// Ctrl-C was pressed
+    pub quit: bool,
+    /// hotkeys pressed since they were last taken
+    pub hotkeys: Vec<Hotkey>,
+    /// Backspace arrived since the last poll
+    pub rewind: bool,
+}
+
+impl CrosstermInput {
+    pub fn new(keymap: Keymap) -> Self {
+        CrosstermInput { keymap, quit: false, hotkeys: vec![], rewind: false }
+    }
+}
+
+impl CrosstermInput {
+    /// Drains pending crossterm events without blocking, returns the keypad state
+    ///
+    /// Unlike an [`InputSource`] it fails when the terminal cannot be read.
+    pub fn poll(&mut self) -> io::Result<u16> {
+        let mut keys = 0;
+        self.rewind = false;
+
+        if !terminal::is_raw_mode_enabled()? {
+            terminal::enable_raw_mode()?;
+        }
+
+        while event::poll(Duration::from_secs(0))? {
+            match event::read()? {
+                Event::Key(KeyEvent { code: KeyCode::Char('c'), modifiers: KeyModifiers::CONTROL, .. }) => {
+                    self.quit = true;
+                }
+                // held down, the key repeats
+                Event::Key(KeyEvent { code: KeyCode::Backspace, .. }) => {
+                    self.rewind = true;
+                }
+                Event::Key(KeyEvent { code: KeyCode::F(number), modifiers, .. }) => {
+                    self.hotkeys.extend(Hotkey::function_key(number, modifiers.contains(KeyModifiers::SHIFT)));
+                }
+                Event::Key(KeyEvent { code: KeyCode::Char(c), modifiers: KeyModifiers::NONE, .. }) => {
+                    if let Some(key) = self.keymap.key_index(c) {
+                        keys |= 1 << key;
+                    }
+                }
+                _ => ()
+            }
+        }
+        Ok(keys)
+    }
+}
+
+/// Rings the terminal bell whenever a beep starts - the fallback without an audio device
+pub struct BellSink {
+    ringing: bool,
+}
+
+impl BellSink {
+    pub fn new() -> Self {
+        BellSink { ringing: false }
+    }
+}
+
+impl AudioSink for BellSink {
+    fn write(&mut self, samples: &[f32]) {
+        let playing = samples.iter().any(|sample| *sample != 0.0);
+        if playing && !self.ringing {
+            let mut stdout = std::io::stdout();
+            let _ = stdout.write_all(b"\x07");
+            let _ = stdout.flush();
+        }
+        self.ringing = playing;
+    }
+}
+
+pub fn print_to_console(display: &Display) -> io::Result<()> {
+    // off, first plane, second plane, both planes
+    let colours: [char; 4] = [' ', '█', '░', '▓'];
+
+    let mut stdout = std::io::stdout();
+
+    let width = display.width();
+
+    for y in 0..display.height() {
+        for x in 0..width {
+            let pixel: char = colours[display.pixel(y * width + x) as usize];
+            crossterm::queue!(stdout,
+                MoveTo(x as u16, y as u16),
+                Print(pixel),
+            )?;
+        }
+    }
+
+    // Make changes visible on the screen
+    stdout.flush()
+}
+
+fn print_notice(line: u16, notice: &str) -> io::Result<()> {
+    let mut stdout = std::io::stdout();
+    crossterm::execute!(stdout,
+        MoveTo(0, line),
+        Clear(ClearType::CurrentLine),
+        Print(notice),
+    )
+}
+
+/// Runs the terminal frontend, `input` replaces the keyboard when given
+pub fn run(machine: &mut Machine, mut input: Option<Box<dyn InputSource>>, mut options: Options, states: &mut Option<StateStore>) -> Result<(), Chip8Error> {
+    let mut terminal_input = CrosstermInput::new(options.keymap);
+    let mut hires = machine.framebuffer().is_hires();
+    let mut previous: Option<Vec<u8>> = None;
+    // an unknown opcode may break into the debugger
+    let mut repl = Repl::for_options(&options);
+    let mut watch = options.watch.clone();
+
+    super::run_paced(options.fast, || {
+        if machine.cpu.exited {
+            return Ok(false);
+        }
+
+        // the terminal is polled regardless, for Ctrl-C
+        let mut keys = terminal_input.poll()?;
+        if terminal_input.quit {
+            return Ok(false);
+        }
+        if let Some(input) = input.as_mut() {
+            keys = input.poll();
+        }
+
+        // below the picture, on a line of its own
+        let notice_line = machine.framebuffer().height() as u16 + 1;
+
+        if terminal_input.rewind {
+            if machine.rewind_frame() {
+                print_notice(notice_line, &format!("Rewound to frame {}", machine.frames))?;
+            }
+        } else if let Some(repl) = repl.as_mut() {
+            if repl.debugger.is_paused() {
+                // the prompt goes below the picture, in cooked mode for line editing
+                crossterm::execute!(std::io::stdout(), MoveTo(0, notice_line + 1), Clear(ClearType::FromCursorDown))?;
+                terminal::disable_raw_mode()?;
+                if !repl.prompt(machine) {
+                    return Ok(false);
+                }
+                crossterm::execute!(std::io::stdout(), Clear(ClearType::All))?;
+                previous = None;
+            }
+            repl.run_frame(machine, keys)?;
+        } else {
+            machine.run_frame(keys)?;
+        }
+
+        for hotkey in terminal_input.hotkeys.drain(..) {
+            print_notice(notice_line, &super::run_hotkey(machine, states.as_ref(), hotkey))?;
+        }
+        for warning in machine.warnings.drain(..) {
+            print_notice(notice_line, &format!("Warning: {}", warning))?;
+        }
+        if let Some(notice) = watch.as_mut().and_then(|watch| watch.poll(machine, states, &mut options.symbols)) {
+            print_notice(notice_line, &notice)?;
+            if let Some(repl) = repl.as_mut() {
+                repl.set_symbols(options.symbols.clone());
+            }
+        }
+        if let Some(trap) = machine.trap.take() {
+            repl.get_or_insert_with(|| Repl::with_symbols(options.symbols.clone())).stop = Some(Stop::Trap(trap));
+        }
+
+        let display = machine.framebuffer();
+        let pixels: Vec<u8> = (0..display.memory.len()).map(|index| display.pixel(index)).collect();
+
+        // only redraw when the picture has changed
+        if previous.as_ref() != Some(&pixels) {
+            // a low resolution picture would leave the rest of a high resolution one behind
+            if hires != display.is_hires() {
+                hires = display.is_hires();
+                crossterm::execute!(std::io::stdout(), Clear(ClearType::All))?;
+            }
+            print_to_console(display)?;
+            previous = Some(pixels);
+        }
+        Ok(true)
+    })
+}
@@ -0,0 +1,183 @@
+use std::io;
+
+use chip8_rs::Machine;
+use chip8_rs::components::display::{WIDTH, HEIGHT};
+use chip8_rs::Chip8Error;
+use chip8_rs::components::input::{InputSource, Keymap};
+use chip8_rs::config::WindowConfig;
+use chip8_rs::state::StateStore;
+use minifb::{Key, KeyRepeat, Scale, Window, WindowOptions};
+
+use chip8_rs::debugger::Stop;
+
+use super::repl::Repl;
+use super::{Hotkey, Options};
+
+const TITLE: &str = "Chip8 - Rust";
+
+pub fn create_window(config: &WindowConfig) -> Result<Window, Chip8Error> {
+    let scale = match config.scale {
+        1 => Scale::X1,
+        2 => Scale::X2,
+        4 => Scale::X4,
+        16 => Scale::X16,
+        32 => Scale::X32,
+        _ => Scale::X8,
+    };
+
+    let mut window = Window::new(
+        TITLE,
+        WIDTH,
+        HEIGHT,
+        WindowOptions {
+            scale,
+            borderless: false,
+            title: true,
+            resize: true,
+            scale_mode: minifb::ScaleMode::Stretch,
+            topmost: config.topmost,
+            transparency: false,
+            none: false,
+        },
+    ).map_err(window_error)?;
+
+    window.set_position(config.x, config.y);
+    // do not set a framerate limit for the display,
+    // the frames are paced by `run_paced`
+    window.limit_update_rate(None);
+    Ok(window)
+}
+
+/// minifb failures, like no display to open the window on, as I/O errors
+fn window_error(error: minifb::Error) -> Chip8Error {
+    io::Error::other(error.to_string()).into()
+}
+
+/// Character printed by a minifb key, used to look up the keypad bindings
+fn key_char(key: Key) -> Option<char> {
+    let c = match key {
+        Key::Key0 => '0', Key::Key1 => '1', Key::Key2 => '2', Key::Key3 => '3', Key::Key4 => '4',
+        Key::Key5 => '5', Key::Key6 => '6', Key::Key7 => '7', Key::Key8 => '8', Key::Key9 => '9',
+        Key::A => 'a', Key::B => 'b', Key::C => 'c', Key::D => 'd', Key::E => 'e', Key::F => 'f',
+        Key::G => 'g', Key::H => 'h', Key::I => 'i', Key::J => 'j', Key::K => 'k', Key::L => 'l',
+        Key::M => 'm', Key::N => 'n', Key::O => 'o', Key::P => 'p', Key::Q => 'q', Key::R => 'r',
+        Key::S => 's', Key::T => 't', Key::U => 'u', Key::V => 'v', Key::W => 'w', Key::X => 'x',
+        Key::Y => 'y', Key::Z => 'z',
+        _ => return None,
+    };
+    Some(c)
+}
+
+fn function_key(key: Key) -> Option<u8> {
+    let number = match key {
+        Key::F1 => 1, Key::F2 => 2, Key::F3 => 3, Key::F4 => 4, Key::F5 => 5,
+        Key::F6 => 6, Key::F7 => 7, Key::F8 => 8, Key::F9 => 9,
+        _ => return None,
+    };
+    Some(number)
+}
+
+/// Keypad fed from the keys held down in a minifb window
+pub struct MinifbInput<'a> {
+    window: &'a Window,
+    keymap: &'a Keymap,
+}
+
+impl<'a> MinifbInput<'a> {
+    pub fn new(window: &'a Window, keymap: &'a Keymap) -> Self {
+        MinifbInput { window, keymap }
+    }
+}
+
+impl InputSource for MinifbInput<'_> {
+    fn poll(&mut self) -> u16 {
+        self.window.get_keys()
+            .into_iter()
+            .filter_map(key_char)
+            .filter_map(|c| self.keymap.key_index(c))
+            .fold(0, |keys, key| keys | 1 << key)
+    }
+}
+
+/// Runs the window frontend, `input` replaces the keyboard when given
+pub fn run(machine: &mut Machine, mut input: Option<Box<dyn InputSource>>, mut options: Options, states: &mut Option<StateStore>) -> Result<(), Chip8Error> {
+    let keymap = options.keymap;
+    let mut window = create_window(&options.window)?;
+    // an unknown opcode may break into the debugger
+    let mut repl = Repl::for_options(&options);
+    let mut watch = options.watch.clone();
+
+    super::run_paced(options.fast, || {
+        if !window.is_open() || window.is_key_down(minifb::Key::Escape) || machine.cpu.exited {
+            return Ok(false);
+        }
+
+        let keys = match input.as_mut() {
+            Some(input) => input.poll(),
+            None => MinifbInput::new(&window, &keymap).poll(),
+        };
+        if window.is_key_down(Key::Backspace) {
+            if machine.rewind_frame() {
+                window.set_title(&format!("{} - Rewound to frame {}", TITLE, machine.frames));
+            }
+        } else if let Some(repl) = repl.as_mut() {
+            // the window freezes while the prompt waits in the terminal
+            if repl.debugger.is_paused() && !repl.prompt(machine) {
+                return Ok(false);
+            }
+            repl.run_frame(machine, keys)?;
+        } else {
+            machine.run_frame(keys)?;
+        }
+
+        let shift = window.is_key_down(Key::LeftShift) || window.is_key_down(Key::RightShift);
+        for key in window.get_keys_pressed(KeyRepeat::No) {
+            if let Some(hotkey) = function_key(key).and_then(|number| Hotkey::function_key(number, shift)) {
+                window.set_title(&format!("{} - {}", TITLE, super::run_hotkey(machine, states.as_ref(), hotkey)));
+            }
+        }
+
+        for warning in machine.warnings.drain(..) {
+            eprintln!("Warning: {}", warning);
+        }
+        if let Some(notice) = watch.as_mut().and_then(|watch| watch.poll(machine, states, &mut options.symbols)) {
+            window.set_title(&format!("{} - {}", TITLE, notice));
+            if let Some(repl) = repl.as_mut() {
+                repl.set_symbols(options.symbols.clone());
+            }
+        }
+        if let Some(trap) = machine.trap.take() {
+            repl.get_or_insert_with(|| Repl::with_symbols(options.symbols.clone())).stop = Some(Stop::Trap(trap));
+        }
+
+        // the resolution may change at any frame (SUPER-CHIP 00FE/00FF)
+        let width = machine.framebuffer().width();
+        let height = machine.framebuffer().height();
+
+        if options.draw {
+            let (w_width, w_heigth) = window.get_size();
+            let width_scale: f32 = w_width as f32 / width as f32;
+            let height_scale: f32 = w_heigth as f32 / height as f32;
+
+            if let Some(mouse) = window.get_unscaled_mouse_pos(minifb::MouseMode::Discard) {
+                let x_coord = (mouse.0 / width_scale).floor() as usize;
+                let y_coord = (mouse.1 / height_scale).floor() as usize;
+
+                if x_coord < width && y_coord < height {
+                    if window.get_mouse_down(minifb::MouseButton::Left) {
+                        machine.cpu.display.memory[x_coord + y_coord * width] = true;
+                    } else if window.get_mouse_down(minifb::MouseButton::Right) {
+                        machine.cpu.display.memory[x_coord + y_coord * width] = false;
+                    }
+                }
+            }
+        }
+
+        let buffer = machine.framebuffer().get_screen_buffer();
+
+        window
+            .update_with_buffer(&buffer, width, height)
+            .map_err(window_error)?;
+        Ok(true)
+    })
+}
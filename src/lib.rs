@@ -0,0 +1,32 @@
+//! Headless CHIP-8 emulator core.
+//!
+//! The library has no terminal or window side effects: frontends feed the
+//! keypad state in, run the [`Machine`] and read the [`Display`] back out.
+//!
+//! ```
+//! use chip8_rs::Machine;
+//!
+//! let mut machine = Machine::new();
+//! machine.load_rom(&[0x00, 0xE0, 0x12, 0x00])?;
+//! let display = machine.run_frame(0)?;
+//! assert!(display.memory.iter().all(|pixel| !pixel));
+//! # Ok::<(), chip8_rs::Chip8Error>(())
+//! ```
+pub mod assembler;
+pub mod clock;
+pub mod components;
+pub mod config;
+pub mod debugger;
+pub mod disasm;
+pub mod error;
+pub mod machine;
+pub mod movie;
+pub mod rewind;
+pub mod rom;
+pub mod screenshot;
+pub mod state;
+pub mod symbols;
+
+pub use components::{cpu::Cpu, display::Display, keypad::Keypad, rand::DoomRNG};
+pub use error::Chip8Error;
+pub use machine::Machine;
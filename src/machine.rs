@@ -0,0 +1,656 @@
+use std::collections::HashSet;
+
+use crate::components::audio::AudioSink;
+use crate::components::input::InputSource;
+use crate::components::{cpu::Cpu, display::Display, platform::Platform, quirks::{Quirks, StackPolicy}};
+use crate::error::Chip8Error;
+use crate::movie::{Movie, MovieFrame};
+use crate::rewind::Rewind;
+use crate::rom;
+use crate::state::{invalid, StateReader, StateWriter, MAGIC, VERSION};
+
+/// Default number of instructions executed per frame
+pub const CYCLES_PER_FRAME: usize = 10;
+/// Frames per second - the timers tick and the audio is rendered once per frame
+pub const FRAME_RATE: u32 = 60;
+
+/// What happens when the program reaches an opcode no platform defines
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum UnknownOpcodePolicy {
+    /// skip it
+    Ignore,
+    /// skip it, reporting each address once in [`Machine::warnings`]
+    Warn,
+    /// stop with [`Chip8Error::UnknownOpcode`]
+    #[default]
+    Halt,
+    /// skip it and stop the frame with [`Machine::trap`] set, for the debugger
+    Break,
+}
+
+impl UnknownOpcodePolicy {
+    /// Names accepted by [`UnknownOpcodePolicy::from_name`]
+    pub const NAMES: [&'static str; 4] = ["ignore", "warn", "halt", "break"];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "ignore" => Some(UnknownOpcodePolicy::Ignore),
+            "warn" => Some(UnknownOpcodePolicy::Warn),
+            "halt" => Some(UnknownOpcodePolicy::Halt),
+            "break" => Some(UnknownOpcodePolicy::Break),
+            _ => None
+        }
+    }
+}
+
+/// What [`Machine::reload_rom`] keeps of the running program
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ReloadMode {
+    /// start the new ROM from scratch, like [`Machine::load_rom`]
+    #[default]
+    Reset,
+    /// swap the code under the running program, keeping the registers, stack and display
+    Keep,
+}
+
+impl ReloadMode {
+    /// Names accepted by [`ReloadMode::from_name`]
+    pub const NAMES: [&'static str; 2] = ["reset", "keep"];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "reset" => Some(ReloadMode::Reset),
+            "keep" => Some(ReloadMode::Keep),
+            _ => None
+        }
+    }
+}
+
+/// Counters of a run
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// instructions executed, unknown ones included
+    pub instructions: u64,
+    /// unknown opcodes reached, whatever the policy did with them
+    pub unknown_opcodes: u64,
+}
+
+/// Embeddable emulator - a [`Cpu`] with a frame-oriented API on top
+///
+/// Time is measured in instructions: every `cycles_per_frame` of them make up
+/// a 60 Hz frame, at the end of which the delay and sound timers tick once.
+pub struct Machine {
+    pub cpu: Cpu,
+    /// instructions per frame, the emulation speed
+    pub cycles_per_frame: usize,
+    /// frames completed since the machine was created
+    pub frames: u64,
+    pub stats: Stats,
+    pub unknown_opcodes: UnknownOpcodePolicy,
+    /// unknown opcodes met under [`UnknownOpcodePolicy::Warn`], for the frontend to show
+    pub warnings: Vec<Chip8Error>,
+    /// set when the program should break into the debugger, `run_frame` returns early
+    pub trap: Option<Chip8Error>,
+    /// snapshots taken at the start of every frame, `None` disables rewinding
+    pub rewind: Option<Rewind>,
+    /// movie every completed frame is appended to
+    pub recording: Option<Movie>,
+    /// movie the keys come from, see [`Machine::play_movie`]
+    pub playback: Option<Movie>,
+    /// addresses already warned about
+    warned: HashSet<u16>,
+    /// hash of the loaded ROM, save states are tied to it
+    rom_hash: u64,
+    /// size of the loaded ROM
+    rom_len: usize,
+    /// instructions executed in the current frame
+    frame_cycles: usize,
+    /// receives the audio rendered at the end of every frame
+    audio_sink: Option<Box<dyn AudioSink>>,
+}
+
+impl Default for Machine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Machine {
+    pub fn new() -> Self {
+        let mut cpu = Cpu::new();
+        cpu.reset();
+        Machine {
+            cpu,
+            cycles_per_frame: CYCLES_PER_FRAME,
+            frames: 0,
+            stats: Stats::default(),
+            unknown_opcodes: UnknownOpcodePolicy::default(),
+            warnings: vec![],
+            trap: None,
+            rewind: None,
+            recording: None,
+            playback: None,
+            warned: HashSet::new(),
+            rom_hash: rom::hash(&[]),
+            rom_len: 0,
+            frame_cycles: 0,
+            audio_sink: None,
+        }
+    }
+
+    pub fn with_quirks(quirks: Quirks) -> Self {
+        let mut machine = Self::new();
+        machine.cpu.quirks = quirks;
+        machine
+    }
+
+    /// Machine for the given platform, using its default quirks
+    pub fn with_platform(platform: Platform) -> Self {
+        let mut machine = Self::with_quirks(platform.default_quirks());
+        machine.cpu.platform = platform;
+        machine.cpu.reset();
+        machine
+    }
+
+    pub fn set_audio_sink(&mut self, sink: Box<dyn AudioSink>) {
+        self.audio_sink = Some(sink);
+    }
+
+    /// Resets the machine and places the ROM at 0x200
+    pub fn load_rom(&mut self, rom: &[u8]) -> Result<(), Chip8Error> {
+        self.cpu.reset();
+        self.trap = None;
+        self.warnings.clear();
+        self.warned.clear();
+        if let Some(rewind) = self.rewind.as_mut() {
+            rewind.clear();
+        }
+        self.rom_hash = rom::hash(rom);
+        self.rom_len = rom.len();
+        self.cpu.load_program(rom.to_vec())
+    }
+
+    /// Replaces the loaded ROM with a new version of it
+    ///
+    /// Under [`ReloadMode::Keep`] only the program memory changes: what is left of a
+    /// longer old ROM is cleared and the rewind history, which belongs to the old code, is dropped.
+    pub fn reload_rom(&mut self, rom: &[u8], mode: ReloadMode) -> Result<(), Chip8Error> {
+        if mode == ReloadMode::Reset {
+            return self.load_rom(rom);
+        }
+
+        self.cpu.load_program(rom.to_vec())?;
+        let start = 0x200 + rom.len();
+        let end = (0x200 + self.rom_len).max(start);
+        self.cpu.memory[start..end].fill(0);
+        self.trap = None;
+        if let Some(rewind) = self.rewind.as_mut() {
+            rewind.clear();
+        }
+        self.rom_hash = rom::hash(rom);
+        self.rom_len = rom.len();
+        Ok(())
+    }
+
+    /// Loads the ROM set up the way the movie was recorded and plays the movie back
+    ///
+    /// The movie then holds the keypad until it ends. Every frame that ends differently
+    /// than it did when recorded sets the `trap` to [`Chip8Error::Desync`] and stops the playback.
+    pub fn play_movie(&mut self, movie: Movie, rom: &[u8]) -> Result<(), Chip8Error> {
+        if rom::hash(rom) != movie.rom_hash {
+            return Err(Chip8Error::InvalidMovie("it was recorded with another ROM".to_string()));
+        }
+
+        self.cpu.platform = movie.platform;
+        self.cpu.quirks = movie.quirks;
+        self.cycles_per_frame = movie.speed;
+        self.frames = 0;
+        self.frame_cycles = 0;
+        self.load_rom(rom)?;
+        self.cpu.random.set_seed(movie.seed);
+        self.playback = Some(movie);
+        Ok(())
+    }
+
+    /// Hash of the whole machine, equal for machines that would behave the same
+    pub fn state_hash(&self) -> u64 {
+        rom::hash(&self.save_state())
+    }
+
+    /// Whether the program has exited or sits on a jump to itself, the way test ROMs end
+    pub fn is_halted(&self) -> bool {
+        let pc = self.cpu.pc as usize;
+        let opcode = self.cpu.memory.get(pc..pc + 2).map(|bytes| (bytes[0] as usize) << 8 | bytes[1] as usize);
+        self.cpu.exited || opcode == Some(0x1000 | pc)
+    }
+
+    /// Hash of the loaded ROM, see [`rom::hash`]
+    pub fn rom_hash(&self) -> u64 {
+        self.rom_hash
+    }
+
+    /// Snapshot of the whole machine, see [`crate::state`]
+    ///
+    /// The output settings (speed, audio sink, volume, palette) are not part of it.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut state = StateWriter::default();
+        state.array(&MAGIC);
+        state.u16(VERSION);
+        state.u64(self.rom_hash);
+        state.u64(self.frames);
+        state.u32(self.frame_cycles as u32);
+        self.cpu.save_state(&mut state);
+        state.finish()
+    }
+
+    /// Restores a [`Machine::save_state`] snapshot of the same ROM
+    ///
+    /// The machine is left untouched when the state is rejected.
+    pub fn load_state(&mut self, bytes: &[u8]) -> Result<(), Chip8Error> {
+        let backup = self.save_state();
+
+        let result = self.read_state(bytes);
+        if result.is_err() {
+            self.read_state(&backup).expect("own state rejected");
+        }
+        result
+    }
+
+    fn read_state(&mut self, bytes: &[u8]) -> Result<(), Chip8Error> {
+        let mut state = StateReader::new(bytes);
+        if state.array::<4>().ok() != Some(MAGIC) {
+            return Err(invalid("it is not a save state"));
+        }
+        let version = state.u16()?;
+        if version != VERSION {
+            return Err(invalid(&format!("it has format version {}, this build reads version {}", version, VERSION)));
+        }
+        if state.u64()? != self.rom_hash {
+            return Err(invalid("it was saved with another ROM"));
+        }
+
+        self.frames = state.u64()?;
+        self.frame_cycles = state.u32()? as usize;
+        self.cpu.load_state(&mut state)?;
+        self.trap = None;
+        state.finish()?;
+
+        // going back in time takes the recording back too, once the state is known to be good
+        if let Some(movie) = self.recording.as_mut() {
+            movie.frames.truncate(self.frames as usize);
+        }
+        Ok(())
+    }
+
+    /// Goes back to the start of the latest recorded frame, `false` when there is none
+    ///
+    /// Calling it again goes back one more frame.
+    pub fn rewind_frame(&mut self) -> bool {
+        let instructions = self.stats.instructions;
+        let Some(rewind) = self.rewind.as_mut() else {
+            return false;
+        };
+
+        // the snapshot of the present, left by a step back to the start of a frame
+        if rewind.latest_instructions() == Some(instructions) {
+            rewind.pop();
+        }
+        match rewind.pop() {
+            Some((instructions, state)) => {
+                self.restore(instructions, &state);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Undoes the last instruction, `false` when it is older than the rewind buffer
+    ///
+    /// Restores the snapshot of its frame and runs the frame up to it again.
+    pub fn step_back(&mut self) -> Result<bool, Chip8Error> {
+        let Some(target) = self.stats.instructions.checked_sub(1) else {
+            return Ok(false);
+        };
+        let Some(rewind) = self.rewind.as_mut() else {
+            return Ok(false);
+        };
+
+        while rewind.latest_instructions().is_some_and(|instructions| instructions > target) {
+            rewind.pop();
+        }
+        let Some((instructions, state)) = rewind.latest().map(|(instructions, state)| (instructions, state.to_vec())) else {
+            return Ok(false);
+        };
+
+        self.restore(instructions, &state);
+        while self.stats.instructions < target {
+            self.step()?;
+        }
+        self.trap = None;
+        Ok(true)
+    }
+
+    fn restore(&mut self, instructions: u64, state: &[u8]) {
+        self.read_state(state).expect("own state rejected");
+        self.stats.instructions = instructions;
+    }
+
+    /// Executes a single instruction, returning its opcode and operation type
+    ///
+    /// Ends the frame after `cycles_per_frame` instructions, or right after a sprite
+    /// draw with the `display_wait` quirk.
+    ///
+    /// Unknown opcodes are handled according to `unknown_opcodes`.
+    pub fn step(&mut self) -> Result<(u16, &'static str), Chip8Error> {
+        if self.frame_cycles == 0 {
+            self.record_frame();
+        }
+
+        let (operation_code, operation_type) = match self.cpu.execute_cycle() {
+            Ok(executed) => executed,
+            Err(error @ Chip8Error::UnknownOpcode { pc, opcode }) => {
+                self.stats.unknown_opcodes += 1;
+                match self.unknown_opcodes {
+                    UnknownOpcodePolicy::Halt => return Err(error),
+                    UnknownOpcodePolicy::Ignore => (),
+                    UnknownOpcodePolicy::Warn => if self.warned.insert(pc) {
+                        self.warnings.push(error);
+                    },
+                    UnknownOpcodePolicy::Break => self.trap = Some(error),
+                }
+                self.cpu.pc = pc.wrapping_add(2);
+                (opcode, "Unknown")
+            }
+            Err(error @ (Chip8Error::StackOverflow { opcode, .. } | Chip8Error::StackUnderflow { opcode, .. }))
+                if self.cpu.quirks.stack_policy == StackPolicy::Trap => {
+                // stay on the instruction, the debugger shows it next
+                self.trap = Some(error);
+                return Ok((opcode, "Flow"));
+            }
+            Err(error) => return Err(error),
+        };
+        self.stats.instructions += 1;
+        self.frame_cycles += 1;
+
+        let vblank_wait = self.cpu.quirks.display_wait && operation_code & 0xF000 == 0xD000;
+        if self.frame_cycles >= self.cycles_per_frame || vblank_wait {
+            self.end_frame();
+        }
+        Ok((operation_code, operation_type))
+    }
+
+    /// Runs until the end of the current frame with the given keypad state
+    ///
+    /// Bit `n` of `keys` set means key `n` is held down.
+    /// Stops early when the program exits or sets the `trap`.
+    pub fn run_frame(&mut self, keys: u16) -> Result<&Display, Chip8Error> {
+        self.set_keys(keys);
+
+        let frame = self.frames;
+        while self.frames == frame && !self.cpu.exited && self.trap.is_none() {
+            self.step()?;
+        }
+        Ok(&self.cpu.display)
+    }
+
+    /// Sets the keypad state, a movie being played back overrides `keys`
+    pub fn set_keys(&mut self, keys: u16) {
+        let recorded = self.playback.as_ref().and_then(|movie| movie.frames.get(self.frames as usize));
+        self.cpu.keypad.set_state(recorded.map_or(keys, |frame| frame.keys));
+    }
+
+    /// Runs until the end of the current frame, polling `input` for the keypad state
+    pub fn run_frame_from(&mut self, input: &mut dyn InputSource) -> Result<&Display, Chip8Error> {
+        let keys = input.poll();
+        self.run_frame(keys)
+    }
+
+    fn record_frame(&mut self) {
+        let instructions = self.stats.instructions;
+        if self.rewind.as_ref().is_none_or(|rewind| rewind.latest_instructions() == Some(instructions)) {
+            return;
+        }
+        let state = self.save_state();
+        if let Some(rewind) = self.rewind.as_mut() {
+            rewind.push(instructions, state);
+        }
+    }
+
+    fn end_frame(&mut self) {
+        if let Some(sink) = self.audio_sink.as_mut() {
+            let count = (self.cpu.audio.sample_rate / FRAME_RATE) as usize;
+            sink.write(&self.cpu.audio.render(self.cpu.st > 0, count));
+        }
+
+        self.cpu.tick_timers();
+        self.frame_cycles = 0;
+        let frame = self.frames;
+        self.frames += 1;
+
+        if self.recording.is_some() || self.playback.is_some() {
+            let hash = self.state_hash();
+            if let Some(movie) = self.recording.as_mut() {
+                movie.frames.push(MovieFrame { keys: self.cpu.keypad.state(), hash });
+            }
+            let recorded = self.playback.as_ref().and_then(|movie| movie.frames.get(frame as usize));
+            if recorded.is_some_and(|recorded| recorded.hash != hash) {
+                self.trap = Some(Chip8Error::Desync { frame });
+                self.playback = None;
+            }
+        }
+    }
+
+    pub fn framebuffer(&self) -> &Display {
+        &self.cpu.display
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::{Machine, ReloadMode, UnknownOpcodePolicy};
+    use crate::movie::Movie;
+    use crate::components::platform::Platform;
+    use crate::error::Chip8Error;
+    use crate::rewind::Rewind;
+
+    #[test]
+    fn it_runs_a_frame() {
+        let mut machine = Machine::new();
+        // V1 = 0x0A, I = font of "A", draw 5 rows at (V0, V0), loop forever
+        machine.load_rom(&[0x61, 0x0A, 0xF1, 0x29, 0xD0, 0x05, 0x12, 0x06]).unwrap();
+
+        let display = machine.run_frame(0).unwrap();
+
+        assert!(display.memory[0], "sprite not drawn");
+        assert_eq!(machine.cpu.pc, 0x206, "pc not looping");
+    }
+
+    #[test]
+    fn it_waits_for_key_without_blocking() {
+        let mut machine = Machine::new();
+        // V3 = key, loop forever
+        machine.load_rom(&[0xF3, 0x0A, 0x12, 0x02]).unwrap();
+
+        machine.run_frame(0).unwrap();
+        assert_eq!(machine.cpu.pc, 0x200, "FX0A does not wait");
+
+        machine.run_frame(1 << 0x7).unwrap();
+        assert_eq!(machine.cpu.pc, 0x200, "FX0A does not wait for release");
+
+        machine.run_frame(0).unwrap();
+        assert_eq!(machine.cpu.v[3], 0x7, "FX0A stores wrong key");
+    }
+
+    #[test]
+    fn it_ticks_timers_once_per_frame() {
+        let mut machine = Machine::new();
+        // V0 = 5, DT = V0, ST = V0, loop forever
+        machine.load_rom(&[0x60, 0x05, 0xF0, 0x15, 0xF0, 0x18, 0x12, 0x06]).unwrap();
+        machine.cycles_per_frame = 20;
+
+        machine.run_frame(0).unwrap();
+        assert_eq!((machine.cpu.dt, machine.cpu.st), (4, 4), "timers do not tick once");
+
+        machine.run_frame(0).unwrap();
+        machine.run_frame(0).unwrap();
+        assert_eq!((machine.cpu.dt, machine.cpu.st), (2, 2), "timers do not tick once per frame");
+        assert_eq!(machine.frames, 3, "wrong frame count");
+    }
+
+    #[test]
+    fn it_beeps_while_the_sound_timer_runs() {
+        let capture = Rc::new(RefCell::new(Vec::<f32>::new()));
+        let mut machine = Machine::new();
+        machine.set_audio_sink(Box::new(Rc::clone(&capture)));
+        // V0 = 2, ST = V0, loop forever
+        machine.load_rom(&[0x60, 0x02, 0xF0, 0x18, 0x12, 0x04]).unwrap();
+
+        for _ in 0..4 {
+            machine.run_frame(0).unwrap();
+        }
+
+        let samples = capture.borrow();
+        let frame = samples.len() / 4;
+        assert!(samples[..frame * 2].iter().all(|sample| *sample != 0.0), "no beep while ST > 0");
+        assert!(samples[frame * 2..].iter().all(|sample| *sample == 0.0), "beep after ST ran out");
+    }
+
+    #[test]
+    fn it_follows_the_unknown_opcode_policy() {
+        let mut machine = Machine::new();
+        // 0NNN machine code call, loop forever
+        let rom = [0x01, 0x23, 0x12, 0x00];
+
+        machine.load_rom(&rom).unwrap();
+        assert!(matches!(machine.run_frame(0), Err(Chip8Error::UnknownOpcode { pc: 0x200, opcode: 0x0123 })), "unknown opcode does not halt");
+
+        machine.load_rom(&rom).unwrap();
+        machine.unknown_opcodes = UnknownOpcodePolicy::Warn;
+        machine.run_frame(0).unwrap();
+        assert_eq!(machine.warnings.len(), 1, "not warned once per address");
+
+        machine.load_rom(&rom).unwrap();
+        machine.unknown_opcodes = UnknownOpcodePolicy::Break;
+        machine.run_frame(0).unwrap();
+        assert!(machine.trap.is_some(), "unknown opcode does not break");
+        assert_eq!(machine.cpu.pc, 0x202, "unknown opcode not skipped");
+
+        assert_eq!(machine.stats.unknown_opcodes, 1 + 5 + 1, "unknown opcodes not counted");
+    }
+
+    #[test]
+    fn it_traps_on_stack_underflow() {
+        let mut machine = Machine::with_platform(Platform::XoChip);
+        // return with nothing to return to
+        machine.load_rom(&[0x00, 0xEE]).unwrap();
+
+        machine.run_frame(0).unwrap();
+        assert!(matches!(machine.trap, Some(Chip8Error::StackUnderflow { pc: 0x200, .. })), "underflow does not trap");
+        assert_eq!(machine.cpu.pc, 0x200, "pc left the trapping instruction");
+    }
+
+    #[test]
+    fn it_saves_and_loads_states() {
+        let mut machine = Machine::new();
+        // V0 += 1, draw, loop forever
+        let rom = [0x70, 0x01, 0xD0, 0x05, 0x12, 0x00];
+        machine.load_rom(&rom).unwrap();
+        machine.run_frame(0).unwrap();
+
+        let state = machine.save_state();
+        let (v, frames) = (machine.cpu.v, machine.frames);
+        let pixels = machine.cpu.display.memory.clone();
+        machine.run_frame(0).unwrap();
+        machine.run_frame(0).unwrap();
+
+        machine.load_state(&state).unwrap();
+        assert_eq!(machine.cpu.v, v, "registers not restored");
+        assert_eq!(machine.frames, frames, "frame count not restored");
+        assert_eq!(machine.cpu.display.memory, pixels, "display not restored");
+        assert_eq!(machine.save_state(), state, "state not restored exactly");
+
+        let mut newer = state.clone();
+        newer[4] = 0xFF;
+        assert!(matches!(machine.load_state(&newer), Err(Chip8Error::InvalidState(_))), "newer format loaded");
+        assert!(matches!(machine.load_state(&state[..40]), Err(Chip8Error::InvalidState(_))), "truncated state loaded");
+        assert_eq!(machine.save_state(), state, "rejected state changed the machine");
+
+        // a rejected state leaves the recording alone, a loaded one takes it back
+        machine.load_rom(&rom).unwrap();
+        machine.recording = Some(Movie::new(&machine));
+        for _ in 0..6 {
+            machine.run_frame(0).unwrap();
+        }
+        assert!(machine.load_state(&state[..40]).is_err(), "truncated state loaded");
+        assert_eq!(machine.recording.as_ref().unwrap().frames.len(), 6, "rejected state cut the recording");
+        machine.load_state(&state).unwrap();
+        assert_eq!(machine.recording.as_ref().unwrap().frames.len(), frames as usize, "recording not taken back");
+
+        machine.load_rom(&[0x12, 0x00]).unwrap();
+        assert!(matches!(machine.load_state(&state), Err(Chip8Error::InvalidState(_))), "state of another ROM loaded");
+    }
+
+    #[test]
+    fn it_rewinds_frames_and_steps() {
+        let mut machine = Machine::new();
+        machine.rewind = Some(Rewind::new(1 << 20));
+        // V0 += 1, loop forever
+        machine.load_rom(&[0x70, 0x01, 0x12, 0x00]).unwrap();
+
+        for _ in 0..3 {
+            machine.run_frame(0).unwrap();
+        }
+        assert_eq!(machine.cpu.v[0], 15, "frames not run");
+
+        assert!(machine.rewind_frame(), "frame not rewound");
+        assert_eq!((machine.cpu.v[0], machine.frames), (10, 2), "not back at the start of the last frame");
+        assert!(machine.rewind_frame(), "frame not rewound");
+        assert_eq!(machine.cpu.v[0], 5, "not back one more frame");
+
+        machine.run_frame(0).unwrap();
+        machine.step().unwrap();
+        machine.step().unwrap();
+        assert_eq!((machine.cpu.v[0], machine.cpu.pc), (11, 0x200), "steps not run");
+
+        assert!(machine.step_back().unwrap(), "step not undone");
+        assert_eq!((machine.cpu.v[0], machine.cpu.pc), (11, 0x202), "wrong instruction undone");
+        assert!(machine.step_back().unwrap(), "step not undone");
+        assert!(machine.step_back().unwrap(), "step into the previous frame not undone");
+        assert_eq!((machine.cpu.v[0], machine.cpu.pc, machine.frames), (10, 0x202, 1), "not back in the previous frame");
+    }
+
+    #[test]
+    fn it_reloads_roms() {
+        let mut machine = Machine::new();
+        // V0 += 1, clear the screen, loop forever
+        machine.load_rom(&[0x70, 0x01, 0x00, 0xE0, 0x12, 0x00]).unwrap();
+        machine.run_frame(0).unwrap();
+        machine.cpu.display.memory[0] = true;
+
+        // V0 += 2, loop forever
+        machine.reload_rom(&[0x70, 0x02, 0x12, 0x00], ReloadMode::Keep).unwrap();
+        assert_eq!(machine.cpu.v[0], 4, "registers reset");
+        assert!(machine.cpu.display.memory[0], "display cleared");
+        assert_eq!(&machine.cpu.memory[0x200..0x206], &[0x70, 0x02, 0x12, 0x00, 0x00, 0x00], "old code left behind");
+        assert_eq!(machine.rom_hash(), crate::rom::hash(&[0x70, 0x02, 0x12, 0x00]), "hash of the old ROM kept");
+
+        machine.reload_rom(&[0x70, 0x02, 0x12, 0x00], ReloadMode::Reset).unwrap();
+        assert_eq!((machine.cpu.v[0], machine.cpu.pc), (0, 0x200), "machine not reset");
+    }
+
+    #[test]
+    fn it_tells_when_the_program_halts() {
+        let mut machine = Machine::with_platform(Platform::SuperChip);
+        // V0 += 1, jump to itself
+        machine.load_rom(&[0x70, 0x01, 0x12, 0x02]).unwrap();
+        assert!(!machine.is_halted(), "halted before running");
+        machine.step().unwrap();
+        assert!(machine.is_halted(), "jump to itself not seen");
+
+        machine.load_rom(&[0x00, 0xFD]).unwrap();
+        machine.step().unwrap();
+        assert!(machine.is_halted(), "exit not seen");
+    }
+}
@@ -1,21 +1,96 @@
-mod components;
-use crate::components::{cpu::Cpu, display};
-use std::{io, time::Duration};
-use components::display::Display;
+mod cli;
+mod frontends;
+use chip8_rs::{Chip8Error, Machine, rom, disasm, assembler::{self, Program}, symbols::Symbols, config::Config, movie::Movie, rewind::Rewind, screenshot::{self, Format}, state::StateStore, components::{
+    audio::{AudioSink, NullSink, WavSink},
+    display::Palette,
+    input::{InputSource, ScriptedInput},
+    platform::Platform}};
+use cli::{Cli, Command, Frontend, HeadlessArgs, RunArgs};
+use clap::Parser;
+use frontends::{Options, Watch, headless::{Limits, Outcome}};
+use std::{io::{self, Write}, path::{Path, PathBuf}, process::ExitCode};
 use crossterm::{
-    style::{style, Stylize, StyledContent}, 
-    terminal::{self, LeaveAlternateScreen, EnterAlternateScreen, EnableLineWrap}, 
-    event::{KeyCode, self, Event, KeyEvent, KeyModifiers}};
+    style::{style, Stylize},
+    terminal::{self, LeaveAlternateScreen, EnterAlternateScreen, EnableLineWrap}};
 use std::fs;
 use inquire::{Select, ui::{RenderConfig, Color, StyleSheet, Styled, Attributes}};
 
+/// Memory for rewinding when neither the command line nor the config set it
+const REWIND_MIB: usize = 16;
+/// Frames the headless frontend runs for by default
+const HEADLESS_FRAMES: u64 = 600;
+/// Frames `test` runs for by default
+const TEST_FRAMES: u64 = 300;
+/// Exit code of runs stopped by an emulation error
+const EXIT_ERROR: u8 = 3;
+/// Exit code of headless runs that ran out of time or instructions
+const EXIT_BUDGET: u8 = 4;
 
-fn main() {
-    let dir_path = "roms/";
-    let files: Vec<String> = fs::read_dir(dir_path)
-        .unwrap()
-        .filter_map(|e| e.ok())
-        .map(|e| e.path().to_string_lossy().into_owned())
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let run_args = match &cli.command {
+        None => Some(&cli.run),
+        Some(Command::Run(args)) => Some(args),
+        _ => None,
+    };
+    if let Some(Err(error)) = run_args.map(RunArgs::check) {
+        error.exit();
+    }
+
+    let config = match cli.config.or_else(Config::path) {
+        Some(path) => match Config::load(&path) {
+            Ok(config) => config,
+            Err(error) => {
+                eprintln!("Cannot load the config: {}", error);
+                return ExitCode::FAILURE;
+            }
+        },
+        None => Config::default(),
+    };
+
+    match cli.command {
+        None => run(cli.run, &config),
+        Some(Command::Run(args)) => run(args, &config),
+        Some(Command::Assemble { source, output, platform }) => assemble(&source, output, platform),
+        Some(Command::Disasm { rom, platform }) => disasm(&rom, platform),
+        Some(Command::Info { rom }) => info(&rom),
+        Some(Command::Test { rom, mut headless, platform, quirks, speed, input }) => {
+            if headless.instructions.is_none() && !headless.until_halt {
+                headless.frames = headless.frames.or(Some(TEST_FRAMES));
+            }
+            let args = RunArgs {
+                rom: Some(rom),
+                frontend: Frontend::Headless,
+                platform,
+                quirks,
+                speed,
+                headless,
+                input,
+                ..RunArgs::default()
+            };
+            run(args, &config)
+        }
+    }
+}
+
+/// Lets the user pick a file from `dirs`
+fn choose_rom(dirs: &[PathBuf]) -> Option<PathBuf> {
+    let mut paths: Vec<PathBuf> = vec![];
+    for dir in dirs {
+        match fs::read_dir(dir) {
+            Ok(entries) => paths.extend(entries.filter_map(|e| e.ok()).map(|e| e.path())),
+            Err(error) => eprintln!("Cannot list \"{}\": {}", dir.display(), error),
+        }
+    }
+    paths.sort();
+
+    // file names are enough when there is a single directory
+    let files: Vec<String> = paths.iter()
+        .map(|path| if dirs.len() == 1 {
+            path.file_name().unwrap_or_default().to_string_lossy().into_owned()
+        } else {
+            path.display().to_string()
+        })
         .collect();
 
     let menu_render_config = RenderConfig::empty()
@@ -33,112 +108,381 @@ fn main() {
             StyleSheet::new()
             .with_fg(Color::LightGreen));
 
-    let menu_dialog = format!("Choose ROM to execute (\"{}\" directory):", dir_path);
+    let dir_names: Vec<String> = dirs.iter().map(|dir| format!("\"{}\"", dir.display())).collect();
+    let menu_dialog = format!("Choose ROM to execute ({} directory):", dir_names.join(", "));
 
-    let menu = Select::new(&menu_dialog, files.iter().map(|s| &s[dir_path.len()..]).collect())
+    let menu = Select::new(&menu_dialog, files)
         .with_render_config(menu_render_config)
         .without_help_message()
         .with_vim_mode(false);
 
-    let file_path = menu.prompt().unwrap();
+    menu.raw_prompt().ok().map(|choice| paths[choice.index].clone())
+}
 
-    let rom = fs::read(String::from(dir_path) + file_path)
-        .expect("Cannot read the file: \"{path}\"");
+fn read_rom(path: &Path) -> Option<Vec<u8>> {
+    match fs::read(path) {
+        Ok(rom) => Some(rom),
+        Err(error) => {
+            eprintln!("Cannot read the file \"{}\": {}", path.display(), error);
+            None
+        }
+    }
+}
 
+/// Assembles the Octo source at `path`
+fn assemble_file(path: &Path, platform: Platform) -> Result<Program, String> {
+    let source = fs::read_to_string(path)
+        .map_err(|error| format!("Cannot read the file \"{}\": {}", path.display(), error))?;
+    assembler::assemble(&source, platform)
+        .map_err(|error| format!("Cannot assemble \"{}\": {}", path.display(), error))
+}
 
-    if cfg!(all(feature = "debug", feature = "alternate-screen")) {
-        eprintln!("Both `debug` and `alternate-screen` are on! The debug won't be shown in the alternate window due to bugs.");
-        println!("You probably want to run cargo with `--no-default-features` flag.");
-    }
+/// Assembles the Octo source at `path`, printing what went wrong if it fails
+fn assemble_source(path: &Path, platform: Platform) -> Option<Program> {
+    assemble_file(path, platform)
+        .map_err(|error| eprintln!("{}", error))
+        .ok()
+}
 
-    if cfg!(feature = "debug") {
-        let debug_message = style("\nROM debug:\n").with(crossterm::style::Color::Yellow);
-        let mut counter_message: StyledContent<String>;
+/// The ROM at `path` as it is now, assembled with its symbols if it is source, for `--watch`
+fn rebuild(path: &Path, platform: Platform) -> Result<(Vec<u8>, Option<Symbols>), String> {
+    let result = match is_source(path) {
+        true => fs::read_to_string(path).map_err(Chip8Error::from)
+            .and_then(|source| assembler::assemble(&source, platform))
+            .map(|program| (program.rom, Some(program.symbols))),
+        false => fs::read(path).map(|rom| (rom, None)).map_err(Chip8Error::from),
+    };
+    result.map_err(|error| error.to_string())
+}
 
-        print!("{}", debug_message);
+/// Whether `path` holds Octo source rather than a ROM
+fn is_source(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| extension == "8o")
+}
 
-        for (counter, value) in rom.iter().enumerate() {
-            if counter % 10 == 0 {
-                counter_message = style(format!("\n{:#03}\t", counter)).with(crossterm::style::Color::Red);
-                print!("{}", counter_message);
+fn run(args: RunArgs, config: &Config) -> ExitCode {
+    let rom_dirs = match &args.rom_dir {
+        Some(dir) => vec![dir.clone()],
+        None if config.rom_dirs.is_empty() => vec![PathBuf::from("roms/")],
+        None => config.rom_dirs.clone(),
+    };
+    let rom_path = match args.rom.clone().or_else(|| choose_rom(&rom_dirs)) {
+        Some(path) => path,
+        None => return ExitCode::FAILURE,
+    };
+    // source runs on the platform it needs, unless told otherwise
+    let target = args.platform.unwrap_or(Platform::XoChip);
+    let (rom, source_platform, mut symbols) = if is_source(&rom_path) {
+        match assemble_source(&rom_path, target) {
+            Some(program) => (program.rom, Some(program.platform), program.symbols),
+            None => return ExitCode::FAILURE,
+        }
+    } else {
+        match read_rom(&rom_path) {
+            Some(rom) => (rom, None, Symbols::new()),
+            None => return ExitCode::FAILURE,
+        }
+    };
+    if let Some(path) = &args.symbols {
+        match Symbols::load(path) {
+            Ok(loaded) => symbols = loaded,
+            Err(error) => {
+                eprintln!("Cannot load the symbols \"{}\": {}", path.display(), error);
+                return ExitCode::FAILURE;
             }
-            print!("{:#04x} ", value);
         }
-        println!("\nConfirm to continue...");
-        let _ = io::stdin().read_line(&mut String::new());
     }
 
-    // Get CPU ready
-    let mut cpu = Cpu::new();
-    cpu.reset();
-    cpu.load_program(rom);
+    // the command line wins over the ROM's section, which wins over the rest of the config
+    let settings = config.for_rom(&rom);
+    let platform = args.platform.or(settings.platform).or(source_platform).unwrap_or_else(|| rom::detect_platform(&rom));
 
+    // Get the machine ready
+    let mut machine = Machine::with_platform(platform);
+    machine.cpu.quirks = args.quirks.or(settings.quirks).unwrap_or(platform.default_quirks());
+    if let Some(depth) = args.stack_depth.or(settings.stack_depth) {
+        machine.cpu.quirks.stack_depth = depth;
+    }
+    if let Some(policy) = args.stack_policy.or(settings.stack_policy) {
+        machine.cpu.quirks.stack_policy = policy;
+    }
+    machine.cpu.display.palette = settings.palette.apply(Palette::default()).with_env();
 
-    let mut stdout = io::stdout();
+    machine.unknown_opcodes = args.unknown_opcodes.or(settings.unknown_opcodes).unwrap_or_default();
 
-    // main game loop inside an alternate screen
-    if cfg!(feature = "alternate-screen") {
-        terminal::enable_raw_mode().unwrap();
-        crossterm::execute!(
-            stdout, 
-            EnterAlternateScreen,
-            EnableLineWrap,
-        ).unwrap();
+    if let Some(speed) = args.speed.or(settings.speed) {
+        machine.cycles_per_frame = speed;
+    }
+    if let Some(frequency) = args.beep {
+        machine.cpu.audio.beep_frequency = frequency;
+    }
+    if let Some(volume) = args.volume {
+        machine.cpu.audio.volume = volume.clamp(0.0, 1.0);
     }
 
-    if cfg!(all(feature = "window", not(feature = "debug"))) {
-        let mut window = Display::create_window();
-
-        while window.is_open() && !window.is_key_down(minifb::Key::Escape) {
-            cpu.execute_cycle();
+    let sink = audio_sink(&mut machine, &args);
+    machine.set_audio_sink(sink);
+    if let Err(error) = machine.load_rom(&rom) {
+        eprintln!("Cannot load \"{}\": {}", rom_path.display(), error);
+        return ExitCode::FAILURE;
+    }
 
-            if cfg!(feature = "drawing") {
-                let (w_width, w_heigth) = window.get_size();
-                let width_scale: f32 = w_width as f32 / display::WIDTH as f32;
-                let height_scale: f32 = w_heigth as f32 / display::HEIGHT as f32;
+    if let Some(seed) = args.seed {
+        machine.cpu.random.set_seed(seed);
+    }
+    if let Some(path) = &args.play {
+        if let Err(error) = Movie::load(path).and_then(|movie| machine.play_movie(movie, &rom)) {
+            eprintln!("Cannot play \"{}\": {}", path.display(), error);
+            return ExitCode::FAILURE;
+        }
+    }
+    if args.record.is_some() {
+        machine.recording = Some(Movie::new(&machine));
+    }
 
-                window.get_unscaled_mouse_pos(minifb::MouseMode::Discard).map(|mouse| {
-                    let x_coord = (mouse.0 / width_scale).floor() as usize;
-                    let y_coord = (mouse.1 / height_scale).floor() as usize;
+    let mut states = StateStore::for_rom(machine.rom_hash());
+    // movies start with the ROM
+    let autosave = (args.autosave || settings.autosave.unwrap_or(false)) && args.record.is_none() && args.play.is_none();
 
-                    if window.get_mouse_down(minifb::MouseButton::Left) {
-                        cpu.display.memory[x_coord + y_coord * display::WIDTH] = true;
-                    } else if window.get_mouse_down(minifb::MouseButton::Right) {
-                        cpu.display.memory[x_coord + y_coord * display::WIDTH] = false;
-                    }
-                });
+    let input: Option<Box<dyn InputSource>> = match &args.input {
+        Some(path) => match ScriptedInput::from_file(path) {
+            Ok(script) => Some(Box::new(script)),
+            Err(error) => {
+                eprintln!("Cannot read the input script: {}", error);
+                return ExitCode::FAILURE;
             }
+        },
+        None => None,
+    };
 
-            let buffer = cpu.display.get_screen_buffer();
+    let options = Options {
+        keymap: settings.keymap.unwrap_or_default(),
+        window: config.window,
+        debug: args.debug,
+        draw: args.draw,
+        fast: args.fast,
+        symbols,
+        watch: args.watch.then(|| Watch::new(rom_path.clone(), args.reload.or(settings.reload).unwrap_or_default(), target, rebuild)),
+    };
 
-            window
-                .update_with_buffer(&buffer, display::WIDTH, display::HEIGHT)
-                .unwrap();
+    if args.frontend == Frontend::Headless {
+        let movie_frames = machine.playback.as_ref().map(|movie| movie.len() as u64);
+        let result = frontends::headless::run(&mut machine, input, limits(&args.headless, movie_frames));
+
+        let format = args.headless.format
+            .or(args.headless.dump.as_deref().and_then(Format::from_path))
+            .unwrap_or_default();
+        let picture = screenshot::encode(machine.framebuffer(), format);
+        let written = match &args.headless.dump {
+            Some(path) => fs::write(path, picture)
+                .map_err(|error| format!("Cannot write \"{}\": {}", path.display(), error)),
+            None => io::stdout().write_all(&picture)
+                .map_err(|error| format!("Cannot print the screen: {}", error)),
+        };
+        let stats = machine.stats;
+        eprintln!("{} frames, {} instructions, {} unknown opcodes", machine.frames, stats.instructions, stats.unknown_opcodes);
+        eprintln!("Framebuffer hash: {:016x}", screenshot::hash(machine.framebuffer()));
+        if result.is_ok() && machine.playback.is_some() {
+            eprintln!("The movie played back in sync");
         }
-    } else {
-        loop {
-            cpu.execute_cycle();
-    
-            if !cfg!(feature = "fast") {
-                // simulate 60hz on the CPU
-                std::thread::sleep(Duration::new(5, 0));
+        save_recording(&mut machine, args.record.as_deref());
+        if let Err(error) = written {
+            eprintln!("{}", error);
+            return ExitCode::FAILURE;
+        }
+        return match result {
+            Ok(Outcome::OutOfBudget(budget)) => {
+                eprintln!("{}", style(format!("Out of the {} budget", budget)).with(crossterm::style::Color::Red));
+                ExitCode::from(EXIT_BUDGET)
             }
-    
-            // Check for Ctrl-C
-            if event::poll(Duration::from_secs(0)).unwrap() {
-                if let Event::Key(KeyEvent { code: KeyCode::Char('c'), modifiers: KeyModifiers::CONTROL, .. }) = event::read().unwrap() {
-                    break
-                }
+            Ok(_) => ExitCode::SUCCESS,
+            Err(error) => report(Err(error), &machine),
+        };
+    }
+
+    let rewind = args.rewind.or(settings.rewind).unwrap_or(REWIND_MIB);
+    if rewind > 0 {
+        machine.rewind = Some(Rewind::new(rewind << 20));
+    }
+
+    if let (true, Some(states)) = (autosave, &states) {
+        match states.resume(&mut machine) {
+            Ok(true) => println!("Resumed from the autosave"),
+            Ok(false) => (),
+            Err(error) => eprintln!("Starting over, {}", error),
+        }
+    }
+
+    // main game loop inside an alternate screen
+    let result = enter_terminal(args.alternate_screen).map_err(Chip8Error::from).and_then(|()| match args.frontend {
+        Frontend::Window => frontends::window::run(&mut machine, input, options, &mut states),
+        _ => frontends::terminal::run(&mut machine, input, options, &mut states),
+    });
+    // Return to normal terminal, whatever happened
+    let result = result.and(leave_terminal(args.alternate_screen).map_err(Chip8Error::from));
+    // an exited program would exit again right after resuming
+    if let (true, Some(states), Ok(())) = (autosave, &states, &result) {
+        if !machine.cpu.exited {
+            if let Err(error) = states.autosave(&machine) {
+                eprintln!("Cannot autosave: {}", error);
             }
         }
     }
+    save_recording(&mut machine, args.record.as_deref());
+    report(result, &machine)
+}
+
+/// What ends a headless run, a movie being played back runs for its length by default
+fn limits(args: &HeadlessArgs, movie_frames: Option<u64>) -> Limits {
+    let frames = match args.frames {
+        Some(frames) => Some(frames),
+        None if args.instructions.is_none() && !args.until_halt => Some(movie_frames.unwrap_or(HEADLESS_FRAMES)),
+        None => None,
+    };
+    Limits {
+        frames,
+        instructions: args.instructions,
+        until_halt: args.until_halt,
+        timeout: args.timeout,
+        max_instructions: args.max_instructions,
+    }
+}
+
+/// Writes the movie recorded by `--record`
+fn save_recording(machine: &mut Machine, path: Option<&Path>) {
+    let (Some(path), Some(movie)) = (path, machine.recording.take()) else {
+        return;
+    };
+    match movie.save(path) {
+        Ok(()) => eprintln!("Recorded {} frames into \"{}\"", movie.len(), path.display()),
+        Err(error) => eprintln!("Cannot save the movie: {}", error),
+    }
+}
 
-    // Return to normal terminal
-    if cfg!(feature = "alternate-screen") {
-        terminal::disable_raw_mode().unwrap();
+/// Prints the error that stopped the emulation with the machine state, if any
+/// Raw mode and the alternate screen, when asked for
+fn enter_terminal(alternate_screen: bool) -> io::Result<()> {
+    if alternate_screen {
+        terminal::enable_raw_mode()?;
         crossterm::execute!(
-            stdout,
+            io::stdout(),
+            EnterAlternateScreen,
+            EnableLineWrap,
+        )?;
+    }
+    Ok(())
+}
+
+/// Undoes [`enter_terminal`], and the raw mode the terminal frontend turns on
+fn leave_terminal(alternate_screen: bool) -> io::Result<()> {
+    if terminal::is_raw_mode_enabled()? {
+        terminal::disable_raw_mode()?;
+    }
+    if alternate_screen {
+        crossterm::execute!(
+            io::stdout(),
             LeaveAlternateScreen,
-        ).unwrap();
+        )?;
+    }
+    Ok(())
+}
+
+fn report(result: Result<(), Chip8Error>, machine: &Machine) -> ExitCode {
+    let Err(error) = result else {
+        return ExitCode::SUCCESS;
+    };
+
+    eprintln!("{}", style(format!("Emulation stopped: {}", error)).with(crossterm::style::Color::Red));
+    if error.pc().is_some() {
+        let cpu = &machine.cpu;
+        eprintln!("\ti={:#05X}, dt={}, st={}", cpu.i, cpu.dt, cpu.st);
+        eprintln!("\tv={:02X?}", cpu.v);
+        eprintln!("\tstack={:03X?}", cpu.stack);
+        eprintln!("\tafter {} frames, {} instructions, {} unknown opcodes",
+            machine.frames, machine.stats.instructions, machine.stats.unknown_opcodes);
+    }
+    ExitCode::from(EXIT_ERROR)
+}
+
+fn assemble(source: &Path, output: Option<PathBuf>, platform: Platform) -> ExitCode {
+    let Some(program) = assemble_source(source, platform) else {
+        return ExitCode::FAILURE;
+    };
+    let output = output.unwrap_or_else(|| source.with_extension("ch8"));
+    let symbols = output.with_extension("sym");
+
+    if let Err(error) = fs::write(&output, &program.rom) {
+        eprintln!("Cannot write \"{}\": {}", output.display(), error);
+        return ExitCode::FAILURE;
+    }
+    if let Err(error) = program.symbols.save(&symbols) {
+        eprintln!("Cannot write \"{}\": {}", symbols.display(), error);
+        return ExitCode::FAILURE;
+    }
+    println!("{} bytes of {} into \"{}\", symbols into \"{}\"",
+        program.rom.len(), Platform::NAMES[program.platform as usize], output.display(), symbols.display());
+    ExitCode::SUCCESS
+}
+
+fn disasm(path: &Path, platform: Option<Platform>) -> ExitCode {
+    let Some(rom) = read_rom(path) else {
+        return ExitCode::FAILURE;
+    };
+    let platform = platform.unwrap_or_else(|| rom::detect_platform(&rom));
+    if rom.len() > rom::max_size(platform) {
+        eprintln!("\"{}\" is too large for {}, which fits {} bytes", path.display(), Platform::NAMES[platform as usize], rom::max_size(platform));
+        return ExitCode::FAILURE;
+    }
+    let listing = disasm::disassemble(&rom, platform);
+
+    let code: usize = listing.lines.iter().filter(|line| line.code).map(|line| line.bytes.len()).sum();
+    println!("; {} as {}, {} of {} bytes reached as code", path.display(), Platform::NAMES[platform as usize], code, rom.len());
+    print!("{}", listing);
+    ExitCode::SUCCESS
+}
+
+fn info(path: &Path) -> ExitCode {
+    let Some(rom) = read_rom(path) else {
+        return ExitCode::FAILURE;
+    };
+    let platform = rom::detect_platform(&rom);
+    let platform_name = Platform::NAMES[platform as usize];
+
+    println!("File:      {}", path.display());
+    println!("Size:      {} bytes", rom.len());
+    println!("Hash:      {:016x}", rom::hash(&rom));
+    println!("Platform:  {} (guessed from the opcodes)", platform_name);
+    for platform in [Platform::Chip8, Platform::SuperChip, Platform::XoChip] {
+        if rom.len() > rom::max_size(platform) {
+            println!("Too large for {}, which fits {} bytes", Platform::NAMES[platform as usize], rom::max_size(platform));
+        }
+    }
+    ExitCode::SUCCESS
+}
+
+/// WAV capture when `--wav` is given, otherwise the audio device,
+/// falling back to the terminal bell for the terminal frontend
+fn audio_sink(machine: &mut Machine, args: &RunArgs) -> Box<dyn AudioSink> {
+    if let Some(path) = &args.wav {
+        match WavSink::create(path, machine.cpu.audio.sample_rate) {
+            Ok(sink) => return Box::new(sink),
+            Err(error) => eprintln!("Cannot create \"{}\": {}", path.display(), error),
+        }
+    }
+
+    if args.frontend == Frontend::Headless {
+        return Box::new(NullSink);
+    }
+
+    #[cfg(feature = "audio-device")]
+    if let Some(speaker) = frontends::speaker::SpeakerSink::open() {
+        machine.cpu.audio.sample_rate = speaker.sample_rate;
+        return Box::new(speaker);
+    }
+
+    if args.frontend == Frontend::Window {
+        Box::new(NullSink)
+    } else {
+        Box::new(frontends::terminal::BellSink::new())
     }
 }
@@ -0,0 +1,186 @@
+//! Input movies for reproducible runs
+//!
+//! A movie holds what the emulation depends on besides the ROM itself -
+//! platform, quirks, speed and RNG seed - followed by the keypad state of
+//! every frame and the [`Machine::state_hash`] the frame ended with.
+//! Playing it back feeds the keys in again and compares the hashes, so the
+//! first frame that turns out differently is known.
+
+use std::fs;
+use std::path::Path;
+
+use crate::components::{platform::Platform, quirks::Quirks};
+use crate::error::Chip8Error;
+use crate::machine::Machine;
+use crate::state::{self, StateReader, StateWriter};
+
+/// Marks the start of every movie
+pub const MAGIC: [u8; 4] = *b"C8MV";
+/// Bumped whenever the layout changes
+pub const VERSION: u16 = 1;
+
+/// Keypad state of a frame and the hash of the machine at its end
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MovieFrame {
+    pub keys: u16,
+    pub hash: u64,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Movie {
+    /// see [`crate::rom::hash`]
+    pub rom_hash: u64,
+    pub platform: Platform,
+    pub quirks: Quirks,
+    /// instructions per frame
+    pub speed: usize,
+    /// see [`crate::components::rand::DoomRNG::seed`]
+    pub seed: u8,
+    /// every frame from the start of the ROM
+    pub frames: Vec<MovieFrame>,
+}
+
+impl Movie {
+    /// Empty movie of the machine as it is set up, meant to be taken right after loading the ROM
+    pub fn new(machine: &Machine) -> Self {
+        Movie {
+            rom_hash : machine.rom_hash(),
+            platform : machine.cpu.platform,
+            quirks : machine.cpu.quirks,
+            speed : machine.cycles_per_frame,
+            seed : machine.cpu.random.seed(),
+            frames : vec![],
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut movie = StateWriter::default();
+        movie.array(&MAGIC);
+        movie.u16(VERSION);
+        // the frame hashes depend on the save state layout
+        movie.u16(state::VERSION);
+        movie.u64(self.rom_hash);
+        self.platform.save_state(&mut movie);
+        self.quirks.save_state(&mut movie);
+        movie.u32(self.speed as u32);
+        movie.u8(self.seed);
+        movie.u32(self.frames.len() as u32);
+        for frame in &self.frames {
+            movie.u16(frame.keys);
+            movie.u64(frame.hash);
+        }
+        movie.finish()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Chip8Error> {
+        Self::read(bytes).map_err(|error| match error {
+            Chip8Error::InvalidState(reason) => Chip8Error::InvalidMovie(reason),
+            error => error,
+        })
+    }
+
+    fn read(bytes: &[u8]) -> Result<Self, Chip8Error> {
+        let mut movie = StateReader::new(bytes);
+        if movie.array::<4>().ok() != Some(MAGIC) {
+            return Err(invalid("it is not a movie"));
+        }
+        let version = movie.u16()?;
+        if version != VERSION {
+            return Err(invalid(&format!("it has format version {}, this build reads version {}", version, VERSION)));
+        }
+        let state_version = movie.u16()?;
+        if state_version != state::VERSION {
+            return Err(invalid(&format!("its frame hashes are of save state version {}, this build makes version {}", state_version, state::VERSION)));
+        }
+
+        let rom_hash = movie.u64()?;
+        let mut platform = Platform::default();
+        platform.load_state(&mut movie)?;
+        let mut quirks = Quirks::default();
+        quirks.load_state(&mut movie)?;
+        let speed = movie.u32()? as usize;
+        let seed = movie.u8()?;
+
+        let len = movie.u32()?;
+        let mut frames = vec![];
+        for _ in 0..len {
+            frames.push(MovieFrame { keys: movie.u16()?, hash: movie.u64()? });
+        }
+        movie.finish()?;
+
+        Ok(Movie { rom_hash, platform, quirks, speed, seed, frames })
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Chip8Error> {
+        Self::from_bytes(&fs::read(path)?)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Chip8Error> {
+        fs::write(path, self.to_bytes())?;
+        Ok(())
+    }
+}
+
+fn invalid(reason: &str) -> Chip8Error {
+    Chip8Error::InvalidMovie(reason.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Movie;
+    use crate::error::Chip8Error;
+    use crate::machine::Machine;
+
+    // V0 = random, V1 = key, draw V0 at (V0, V0), loop back to the key
+    const ROM: [u8; 10] = [0xC0, 0xFF, 0xF1, 0x0A, 0xF0, 0x29, 0xD0, 0x05, 0x12, 0x02];
+
+    fn record(keys: &[u16]) -> Movie {
+        let mut machine = Machine::new();
+        machine.load_rom(&ROM).unwrap();
+        machine.cpu.random.set_seed(42);
+        machine.recording = Some(Movie::new(&machine));
+        for keys in keys {
+            machine.run_frame(*keys).unwrap();
+        }
+        machine.recording.take().unwrap()
+    }
+
+    #[test]
+    fn it_plays_back_a_recording() {
+        let movie = Movie::from_bytes(&record(&[0, 1 << 3, 0, 1 << 5, 0, 0]).to_bytes()).unwrap();
+        assert_eq!(movie.len(), 6, "frames not recorded");
+        assert_eq!(movie.seed, 42, "seed not recorded");
+
+        let mut machine = Machine::new();
+        machine.play_movie(movie, &ROM).unwrap();
+        for _ in 0..6 {
+            // the movie holds the keys
+            machine.run_frame(0).unwrap();
+            assert!(machine.trap.is_none(), "desynced");
+        }
+        assert_eq!(machine.cpu.v[1], 5, "keys not played back");
+    }
+
+    #[test]
+    fn it_reports_the_first_desync() {
+        let mut movie = record(&[0, 1 << 3, 0, 0]);
+        movie.frames[2].hash ^= 1;
+
+        let mut machine = Machine::new();
+        assert!(matches!(machine.play_movie(movie.clone(), &[0x12, 0x00]), Err(Chip8Error::InvalidMovie(_))), "movie of another ROM played");
+
+        machine.play_movie(movie, &ROM).unwrap();
+        for _ in 0..4 {
+            machine.run_frame(0).unwrap();
+        }
+        assert!(matches!(machine.trap, Some(Chip8Error::Desync { frame: 2 })), "desync not reported");
+    }
+}
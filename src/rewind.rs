@@ -0,0 +1,219 @@
+//! Rewind buffer of per-frame snapshots
+//!
+//! Only the latest snapshot is kept whole. Every older one is stored as the
+//! difference to the snapshot after it, XORed and run-length encoded, so a
+//! frame that changed a handful of bytes costs a handful of bytes. The oldest
+//! snapshots are dropped once the buffer outgrows its memory budget.
+
+use std::collections::VecDeque;
+
+/// Snapshot of the machine tagged with the instruction count it was taken at
+struct Snapshot {
+    instructions: u64,
+    state: Vec<u8>,
+}
+
+/// Ring buffer of [`Machine::save_state`](crate::Machine::save_state) snapshots
+pub struct Rewind {
+    /// bytes the snapshots may take up
+    pub budget: usize,
+    latest: Option<Snapshot>,
+    /// older snapshots, oldest first, each encoded against the one after it
+    deltas: VecDeque<Snapshot>,
+    used: usize,
+}
+
+impl Rewind {
+    pub fn new(budget: usize) -> Self {
+        Rewind {
+            budget,
+            latest : None,
+            deltas : VecDeque::new(),
+            used : 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.deltas.len() + self.latest.is_some() as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.latest.is_none()
+    }
+
+    /// Bytes taken up by the snapshots
+    pub fn memory_used(&self) -> usize {
+        self.used
+    }
+
+    pub fn clear(&mut self) {
+        self.latest = None;
+        self.deltas.clear();
+        self.used = 0;
+    }
+
+    /// Instruction count of the latest snapshot
+    pub fn latest_instructions(&self) -> Option<u64> {
+        self.latest.as_ref().map(|snapshot| snapshot.instructions)
+    }
+
+    /// The latest snapshot, left in the buffer
+    pub fn latest(&self) -> Option<(u64, &[u8])> {
+        self.latest.as_ref().map(|snapshot| (snapshot.instructions, &snapshot.state[..]))
+    }
+
+    /// Adds a snapshot, dropping the oldest ones past the budget
+    pub fn push(&mut self, instructions: u64, state: Vec<u8>) {
+        if let Some(previous) = self.latest.take() {
+            let delta = encode(&state, &previous.state);
+            self.used = self.used - previous.state.len() + delta.len();
+            self.deltas.push_back(Snapshot { instructions: previous.instructions, state: delta });
+        }
+        self.used += state.len();
+        self.latest = Some(Snapshot { instructions, state });
+
+        while self.used > self.budget {
+            match self.deltas.pop_front() {
+                Some(oldest) => self.used -= oldest.state.len(),
+                None => {
+                    // not even a single snapshot fits
+                    self.clear();
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Takes the latest snapshot out, the one before it becomes the latest
+    pub fn pop(&mut self) -> Option<(u64, Vec<u8>)> {
+        let latest = self.latest.take()?;
+        self.used -= latest.state.len();
+
+        if let Some(delta) = self.deltas.pop_back() {
+            let state = decode(&latest.state, &delta.state);
+            self.used = self.used - delta.state.len() + state.len();
+            self.latest = Some(Snapshot { instructions: delta.instructions, state });
+        }
+        Some((latest.instructions, latest.state))
+    }
+}
+
+/// Encodes `target` against `base` as runs of unchanged bytes followed by XORed literals
+///
+/// Both run lengths are LEB128, the target's length comes first.
+fn encode(base: &[u8], target: &[u8]) -> Vec<u8> {
+    let xored: Vec<u8> = target.iter()
+        .enumerate()
+        .map(|(index, byte)| byte ^ base.get(index).copied().unwrap_or(0))
+        .collect();
+
+    let mut delta = vec![];
+    write_length(&mut delta, target.len());
+
+    let mut rest = &xored[..];
+    while !rest.is_empty() {
+        let unchanged = rest.iter().take_while(|byte| **byte == 0).count();
+        let changed = rest[unchanged..].iter().take_while(|byte| **byte != 0).count();
+        write_length(&mut delta, unchanged);
+        write_length(&mut delta, changed);
+        delta.extend_from_slice(&rest[unchanged..unchanged + changed]);
+        rest = &rest[unchanged + changed..];
+    }
+    delta
+}
+
+/// Inverse of [`encode`]
+fn decode(base: &[u8], delta: &[u8]) -> Vec<u8> {
+    let mut delta = delta;
+    let len = read_length(&mut delta);
+    let mut target: Vec<u8> = (0..len).map(|index| base.get(index).copied().unwrap_or(0)).collect();
+
+    let mut position = 0;
+    while !delta.is_empty() {
+        position += read_length(&mut delta);
+        let changed = read_length(&mut delta);
+        for (byte, xor) in target[position..position + changed].iter_mut().zip(&delta[..changed]) {
+            *byte ^= xor;
+        }
+        position += changed;
+        delta = &delta[changed..];
+    }
+    target
+}
+
+fn write_length(bytes: &mut Vec<u8>, mut value: usize) {
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+        if value == 0 {
+            bytes.push(byte);
+            return;
+        }
+        bytes.push(byte | 0x80);
+    }
+}
+
+fn read_length(bytes: &mut &[u8]) -> usize {
+    let mut value = 0;
+    let mut shift = 0;
+    while let Some((&byte, rest)) = bytes.split_first() {
+        *bytes = rest;
+        value |= ((byte & 0x7F) as usize) << shift;
+        shift += 7;
+        if byte & 0x80 == 0 {
+            break;
+        }
+    }
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode, encode, Rewind};
+
+    #[test]
+    fn it_encodes_differences() {
+        let base = vec![0u8; 300];
+        let mut target = base.clone();
+        target[5] = 1;
+        target[200] = 2;
+        target.push(3);
+
+        let delta = encode(&base, &target);
+        assert!(delta.len() < 16, "unchanged bytes not compressed");
+        assert_eq!(decode(&base, &delta), target, "wrong bytes decoded");
+        assert_eq!(decode(&target, &encode(&target, &base)), base, "shorter target not decoded");
+    }
+
+    #[test]
+    fn it_pops_in_reverse_order() {
+        let snapshot = |frame: u8| {
+            let mut state = vec![0; 100];
+            state[frame as usize] = frame;
+            state
+        };
+        let mut rewind = Rewind::new(usize::MAX);
+        for frame in 0..5u8 {
+            rewind.push(frame as u64 * 10, snapshot(frame));
+        }
+        assert!(rewind.memory_used() < 2 * 100, "snapshots not compressed");
+
+        for frame in (0..5u8).rev() {
+            assert_eq!(rewind.pop(), Some((frame as u64 * 10, snapshot(frame))), "wrong snapshot popped");
+        }
+        assert_eq!(rewind.pop(), None, "empty buffer popped");
+        assert_eq!(rewind.memory_used(), 0, "memory not given back");
+    }
+
+    #[test]
+    fn it_stays_within_the_budget() {
+        let mut rewind = Rewind::new(1000);
+        for frame in 0..100u8 {
+            rewind.push(frame as u64, vec![frame; 400]);
+        }
+
+        assert!(rewind.memory_used() <= 1000, "budget exceeded");
+        assert!(rewind.len() > 1, "older snapshots dropped");
+        assert_eq!(rewind.pop().map(|(instructions, _)| instructions), Some(99), "latest snapshot dropped");
+    }
+}
@@ -0,0 +1,62 @@
+//! ROM inspection helpers
+
+use crate::components::platform::Platform;
+
+/// Where programs are loaded and start executing
+pub const START_ADDRESS: usize = 0x200;
+
+/// FNV-1a hash of the ROM contents, identifies a ROM regardless of its file name
+pub fn hash(rom: &[u8]) -> u64 {
+    rom.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Biggest ROM the platform can load
+pub fn max_size(platform: Platform) -> usize {
+    platform.memory_size() - START_ADDRESS
+}
+
+/// Best guess of the platform a ROM was written for
+///
+/// Looks for instructions that only exist in the extensions. Data bytes may
+/// look like such instructions too, so treat the result as a hint.
+pub fn detect_platform(rom: &[u8]) -> Platform {
+    if rom.len() > max_size(Platform::Chip8) {
+        return Platform::XoChip;
+    }
+
+    let mut platform = Platform::Chip8;
+    for opcode in rom.chunks_exact(2).map(|pair| (pair[0] as u16) << 8 | pair[1] as u16) {
+        match opcode {
+            // long I load, audio pattern, scroll up, plane selection, pitch
+            0xF000 | 0xF002 => return Platform::XoChip,
+            _ if opcode & 0xFFF0 == 0x00D0 => return Platform::XoChip,
+            _ if opcode & 0xF0FF == 0xF001 || opcode & 0xF0FF == 0xF03A => return Platform::XoChip,
+            // hires, lores, scrolling, exit, RPL flags
+            0x00FB..=0x00FF => platform = Platform::SuperChip,
+            _ if opcode & 0xF0FF == 0xF075 || opcode & 0xF0FF == 0xF085 => platform = Platform::SuperChip,
+            _ => ()
+        }
+    }
+    platform
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{detect_platform, hash};
+    use crate::components::platform::Platform;
+
+    #[test]
+    fn it_hashes_contents() {
+        assert_eq!(hash(&[]), 0xcbf2_9ce4_8422_2325, "wrong empty hash");
+        assert_ne!(hash(&[0x12, 0x00]), hash(&[0x00, 0x12]), "order does not matter");
+    }
+
+    #[test]
+    fn it_detects_platform() {
+        assert_eq!(detect_platform(&[0x00, 0xE0, 0x12, 0x00]), Platform::Chip8);
+        assert_eq!(detect_platform(&[0x00, 0xFF, 0x12, 0x00]), Platform::SuperChip);
+        assert_eq!(detect_platform(&[0x00, 0xFF, 0xF0, 0x00, 0x12, 0x34]), Platform::XoChip);
+    }
+}
@@ -0,0 +1,176 @@
+//! Pictures of the [`Display`] for headless runs
+//!
+//! PNGs keep the four palette colours, plain PBM and the ASCII grid only tell
+//! pixels apart, so they diff well as golden images.
+
+use std::path::Path;
+
+use crate::components::display::Display;
+use crate::rom;
+
+/// File formats the screen can be written in
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Format {
+    Png,
+    /// plain (`P1`) portable bitmap, a pixel is set on either plane
+    Pbm,
+    /// one line per row, see [`ascii`]
+    #[default]
+    Ascii,
+}
+
+impl Format {
+    /// Names accepted by [`Format::from_name`]
+    pub const NAMES: [&'static str; 3] = ["png", "pbm", "ascii"];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "png" => Some(Format::Png),
+            "pbm" => Some(Format::Pbm),
+            "ascii" | "txt" => Some(Format::Ascii),
+            _ => None
+        }
+    }
+
+    /// Format named by the extension of `path`
+    pub fn from_path(path: &Path) -> Option<Self> {
+        Self::from_name(path.extension()?.to_str()?)
+    }
+}
+
+/// The screen in the given format
+pub fn encode(display: &Display, format: Format) -> Vec<u8> {
+    match format {
+        Format::Png => png(display),
+        Format::Pbm => pbm(display).into_bytes(),
+        Format::Ascii => ascii(display).into_bytes(),
+    }
+}
+
+/// Hash of the resolution and the pixels, the palette left out
+pub fn hash(display: &Display) -> u64 {
+    let mut bytes = vec![display.width() as u8, display.height() as u8];
+    bytes.extend((0..display.memory.len()).map(|index| display.pixel(index)));
+    rom::hash(&bytes)
+}
+
+/// The screen as text, one line per row
+pub fn ascii(display: &Display) -> String {
+    // off, first plane, second plane, both planes
+    let colours: [char; 4] = ['.', '#', '+', '@'];
+    let width = display.width();
+
+    (0..display.height())
+        .map(|y| (0..width).map(|x| colours[display.pixel(y * width + x) as usize]).collect::<String>() + "\n")
+        .collect()
+}
+
+/// Plain PBM, rows split into lines of 64 pixels to stay under the 70 characters the format allows
+pub fn pbm(display: &Display) -> String {
+    let (width, height) = (display.width(), display.height());
+    let mut text = format!("P1\n{} {}\n", width, height);
+    for y in 0..height {
+        for chunk in (0..width).collect::<Vec<_>>().chunks(64) {
+            text.extend(chunk.iter().map(|x| if display.pixel(y * width + x) == 0 { '0' } else { '1' }));
+            text.push('\n');
+        }
+    }
+    text
+}
+
+/// PNG with a pixel per pixel, indexed into the display's palette
+///
+/// The image data is stored without compression, screens are a few KiB at most.
+pub fn png(display: &Display) -> Vec<u8> {
+    let (width, height) = (display.width(), display.height());
+
+    let mut header = vec![];
+    header.extend((width as u32).to_be_bytes());
+    header.extend((height as u32).to_be_bytes());
+    // 8 bits per pixel, indexed colour, deflate, adaptive filtering, no interlace
+    header.extend([8, 3, 0, 0, 0]);
+
+    let palette: Vec<u8> = display.palette.colours().iter()
+        .flat_map(|colour| [(colour >> 16) as u8, (colour >> 8) as u8, *colour as u8])
+        .collect();
+
+    // every row starts with filter type 0
+    let mut pixels = vec![];
+    for y in 0..height {
+        pixels.push(0);
+        pixels.extend((0..width).map(|x| display.pixel(y * width + x)));
+    }
+
+    let mut png = vec![0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+    chunk(&mut png, b"IHDR", &header);
+    chunk(&mut png, b"PLTE", &palette);
+    chunk(&mut png, b"IDAT", &zlib_stored(&pixels));
+    chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+fn chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend((data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend(kind);
+    png.extend(data);
+    let crc = crc32(&png[start..]);
+    png.extend(crc.to_be_bytes());
+}
+
+/// zlib stream of uncompressed deflate blocks
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut stream = vec![0x78, 0x01];
+    let blocks: Vec<&[u8]> = if data.is_empty() { vec![&[]] } else { data.chunks(0xFFFF).collect() };
+    for (index, block) in blocks.iter().enumerate() {
+        let last = index + 1 == blocks.len();
+        stream.push(last as u8);
+        stream.extend((block.len() as u16).to_le_bytes());
+        stream.extend((!(block.len() as u16)).to_le_bytes());
+        stream.extend(*block);
+    }
+    stream.extend(adler32(data).to_be_bytes());
+    stream
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0u32, |crc, byte| {
+        (0..8).fold(crc ^ *byte as u32, |crc, _| if crc & 1 != 0 { crc >> 1 ^ 0xEDB8_8320 } else { crc >> 1 })
+    })
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (a, b) = data.iter().fold((1u32, 0u32), |(a, b), byte| {
+        let a = (a + *byte as u32) % 65521;
+        (a, (b + a) % 65521)
+    });
+    b << 16 | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{crc32, hash, pbm, png, Format};
+    use crate::components::display::Display;
+    use std::path::Path;
+
+    #[test]
+    fn it_writes_the_screen() {
+        let mut display = Display::new();
+        let blank = hash(&display);
+        display.memory[1] = true;
+        assert_ne!(hash(&display), blank, "pixel not hashed");
+
+        let text = pbm(&display);
+        assert!(text.starts_with("P1\n64 32\n01000"), "wrong header or pixels: {}", &text[..16]);
+        assert_eq!(text.lines().count(), 2 + 32, "rows not one line each");
+
+        let png = png(&display);
+        assert_eq!(&png[1..4], b"PNG");
+        // the crc of IEND, always the same
+        assert_eq!(&png[png.len() - 4..], &crc32(b"IEND").to_be_bytes());
+        assert_eq!(crc32(b"IEND"), 0xAE42_6082, "wrong checksum");
+
+        assert_eq!(Format::from_path(Path::new("out/screen.PBM")), Some(Format::Pbm));
+        assert_eq!(Format::from_path(Path::new("screen")), None);
+    }
+}
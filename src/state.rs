@@ -0,0 +1,232 @@
+//! Versioned save states
+//!
+//! A state is a little-endian binary snapshot of the [`Machine`](crate::Machine):
+//! the `C8RS` magic, the format [`VERSION`], the hash of the ROM it was taken
+//! with, then every component in a fixed order. States of another version or
+//! another ROM are rejected with [`Chip8Error::InvalidState`].
+
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+use crate::error::Chip8Error;
+use crate::machine::Machine;
+
+/// Marks the start of every save state
+pub const MAGIC: [u8; 4] = *b"C8RS";
+/// Bumped whenever the layout changes, older states are then rejected
+pub const VERSION: u16 = 1;
+/// Slots reachable from the hotkeys, numbered from 1
+pub const SLOTS: u8 = 9;
+
+/// Builds the state byte by byte
+#[derive(Default)]
+pub(crate) struct StateWriter {
+    bytes: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    pub fn bool(&mut self, value: bool) {
+        self.u8(value as u8);
+    }
+
+    pub fn u16(&mut self, value: u16) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn u32(&mut self, value: u32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn u64(&mut self, value: u64) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    /// Fixed-size data, the reader has to know the length
+    pub fn array(&mut self, values: &[u8]) {
+        self.bytes.extend_from_slice(values);
+    }
+
+    /// Length-prefixed data
+    pub fn bytes(&mut self, values: &[u8]) {
+        self.u32(values.len() as u32);
+        self.array(values);
+    }
+
+    /// Length-prefixed, eight flags per byte
+    pub fn bools(&mut self, values: &[bool]) {
+        self.u32(values.len() as u32);
+        for chunk in values.chunks(8) {
+            self.u8(chunk.iter().enumerate().fold(0, |byte, (bit, value)| byte | (*value as u8) << bit));
+        }
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.bytes
+    }
+}
+
+/// Reads a state back in the order it was written
+pub(crate) struct StateReader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        StateReader { bytes }
+    }
+
+    pub fn array<const N: usize>(&mut self) -> Result<[u8; N], Chip8Error> {
+        if self.bytes.len() < N {
+            return Err(invalid("it is truncated"));
+        }
+        let (value, rest) = self.bytes.split_at(N);
+        self.bytes = rest;
+        Ok(value.try_into().unwrap())
+    }
+
+    pub fn u8(&mut self) -> Result<u8, Chip8Error> {
+        Ok(self.array::<1>()?[0])
+    }
+
+    pub fn bool(&mut self) -> Result<bool, Chip8Error> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            value => Err(invalid(&format!("{} is not a flag", value))),
+        }
+    }
+
+    pub fn u16(&mut self) -> Result<u16, Chip8Error> {
+        Ok(u16::from_le_bytes(self.array()?))
+    }
+
+    pub fn u32(&mut self) -> Result<u32, Chip8Error> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, Chip8Error> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+
+    pub fn bytes(&mut self) -> Result<Vec<u8>, Chip8Error> {
+        let len = self.u32()? as usize;
+        if self.bytes.len() < len {
+            return Err(invalid("it is truncated"));
+        }
+        let (value, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(value.to_vec())
+    }
+
+    pub fn bools(&mut self) -> Result<Vec<bool>, Chip8Error> {
+        let len = self.u32()? as usize;
+        if self.bytes.len() < len.div_ceil(8) {
+            return Err(invalid("it is truncated"));
+        }
+        let mut values = Vec::with_capacity(len);
+        for _ in 0..len.div_ceil(8) {
+            let byte = self.u8()?;
+            values.extend((0..8).map(|bit| byte & (1 << bit) != 0));
+        }
+        values.truncate(len);
+        Ok(values)
+    }
+
+    /// Everything has been read
+    pub fn finish(self) -> Result<(), Chip8Error> {
+        if self.bytes.is_empty() {
+            Ok(())
+        } else {
+            Err(invalid("it has trailing data"))
+        }
+    }
+}
+
+pub(crate) fn invalid(reason: &str) -> Chip8Error {
+    Chip8Error::InvalidState(reason.to_string())
+}
+
+/// Save state files of a single ROM
+///
+/// Kept in `$XDG_DATA_HOME/chip8-rs/states/<ROM hash>/` (or the platform's equivalent).
+pub struct StateStore {
+    pub dir: PathBuf,
+}
+
+impl StateStore {
+    pub fn new(dir: PathBuf) -> Self {
+        StateStore { dir }
+    }
+
+    /// The default store of the ROM with the given hash
+    pub fn for_rom(hash: u64) -> Option<Self> {
+        dirs::data_dir().map(|dir| Self::new(dir.join("chip8-rs").join("states").join(format!("{:016x}", hash))))
+    }
+
+    fn slot_path(&self, slot: u8) -> PathBuf {
+        self.dir.join(format!("slot{}.state", slot))
+    }
+
+    fn autosave_path(&self) -> PathBuf {
+        self.dir.join("autosave.state")
+    }
+
+    pub fn save(&self, slot: u8, machine: &Machine) -> Result<(), Chip8Error> {
+        self.write(self.slot_path(slot), machine)
+    }
+
+    pub fn load(&self, slot: u8, machine: &mut Machine) -> Result<(), Chip8Error> {
+        machine.load_state(&fs::read(self.slot_path(slot))?)
+    }
+
+    pub fn autosave(&self, machine: &Machine) -> Result<(), Chip8Error> {
+        self.write(self.autosave_path(), machine)
+    }
+
+    /// Loads the autosave, `false` when there is none
+    pub fn resume(&self, machine: &mut Machine) -> Result<bool, Chip8Error> {
+        match fs::read(self.autosave_path()) {
+            Ok(state) => machine.load_state(&state).map(|_| true),
+            Err(error) if error.kind() == ErrorKind::NotFound => Ok(false),
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    fn write(&self, path: PathBuf, machine: &Machine) -> Result<(), Chip8Error> {
+        fs::create_dir_all(&self.dir)?;
+        fs::write(path, machine.save_state())?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{StateReader, StateWriter};
+
+    #[test]
+    fn it_reads_what_was_written() {
+        let mut writer = StateWriter::default();
+        writer.u8(7);
+        writer.u16(0x1234);
+        writer.bools(&[true, false, true, true, false, false, false, false, true]);
+        writer.bytes(&[1, 2, 3]);
+        let bytes = writer.finish();
+
+        let mut reader = StateReader::new(&bytes);
+        assert_eq!(reader.u8().unwrap(), 7, "wrong byte read");
+        assert_eq!(reader.u16().unwrap(), 0x1234, "wrong word read");
+        assert_eq!(reader.bools().unwrap(), vec![true, false, true, true, false, false, false, false, true], "wrong flags read");
+        assert_eq!(reader.bytes().unwrap(), vec![1, 2, 3], "wrong bytes read");
+        assert!(reader.finish().is_ok(), "trailing data left");
+
+        assert!(StateReader::new(&bytes[..2]).u16().is_ok(), "word not read");
+        assert!(StateReader::new(&bytes[..1]).u16().is_err(), "truncated word read");
+        // a length no state could hold is not allocated
+        assert!(StateReader::new(&[0xFF, 0xFF, 0xFF, 0xFF, 1]).bools().is_err(), "truncated flags read");
+    }
+}
@@ -0,0 +1,104 @@
+//! Symbol maps
+//!
+//! Names of addresses written by the assembler next to the ROM and read back by
+//! the debugger. One symbol per line:
+//!
+//! ```text
+//! label main 0x202
+//! breakpoint check 0x21A
+//! ```
+
+use std::fs;
+use std::path::Path;
+
+use crate::disasm::Labels;
+use crate::error::Chip8Error;
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Symbols {
+    /// labels in the order they were defined
+    pub labels: Vec<(String, u16)>,
+    /// places the program asks the debugger to stop at
+    pub breakpoints: Vec<(String, u16)>,
+}
+
+impl Symbols {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Address of the label or breakpoint `name`
+    pub fn address(&self, name: &str) -> Option<u16> {
+        self.labels.iter().chain(&self.breakpoints).find(|(symbol, _)| symbol == name).map(|(_, address)| *address)
+    }
+
+    /// The labels by address, the first one defined wins where several share one
+    pub fn by_address(&self) -> Labels {
+        let mut labels = Labels::new();
+        for (name, address) in &self.labels {
+            labels.entry(*address).or_insert_with(|| name.clone());
+        }
+        labels
+    }
+
+    pub fn to_text(&self) -> String {
+        let labels = self.labels.iter().map(|symbol| ("label", symbol));
+        let breakpoints = self.breakpoints.iter().map(|symbol| ("breakpoint", symbol));
+        labels.chain(breakpoints)
+            .map(|(kind, (name, address))| format!("{} {} {:#05X}\n", kind, name, address))
+            .collect()
+    }
+
+    pub fn from_text(text: &str) -> Result<Self, Chip8Error> {
+        let mut symbols = Symbols::new();
+        for (index, line) in text.lines().enumerate() {
+            let syntax = |message: &str| Chip8Error::Syntax { line : index + 1, message : message.to_string() };
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let (kind, name, address) = match words[..] {
+                [] => continue,
+                [kind, name, address] => (kind, name, address),
+                _ => return Err(syntax("expected a kind, a name and an address")),
+            };
+            let address = match address.strip_prefix("0x") {
+                Some(hex) => u16::from_str_radix(hex, 16),
+                None => address.parse(),
+            }.map_err(|_| syntax(&format!("\"{}\" is not an address", address)))?;
+
+            match kind {
+                "label" => symbols.labels.push((name.to_string(), address)),
+                "breakpoint" => symbols.breakpoints.push((name.to_string(), address)),
+                _ => return Err(syntax(&format!("\"{}\" is neither label nor breakpoint", kind))),
+            }
+        }
+        Ok(symbols)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Chip8Error> {
+        Self::from_text(&fs::read_to_string(path)?)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Chip8Error> {
+        fs::write(path, self.to_text())?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Symbols;
+    use crate::error::Chip8Error;
+
+    #[test]
+    fn it_reads_what_was_written() {
+        let symbols = Symbols {
+            labels : vec![("main".to_string(), 0x200), ("loop".to_string(), 0x200), ("draw".to_string(), 0x20A)],
+            breakpoints : vec![("check".to_string(), 0x204)],
+        };
+        assert_eq!(Symbols::from_text(&symbols.to_text()).unwrap(), symbols, "symbols changed");
+        assert_eq!(symbols.by_address()[&0x200], "main", "later label won");
+        assert_eq!(symbols.address("draw"), Some(0x20A));
+        assert_eq!(symbols.address("check"), Some(0x204), "breakpoint not found by name");
+
+        assert!(matches!(Symbols::from_text("label main 0x200\nlabel oops\n"), Err(Chip8Error::Syntax { line: 2, .. })), "malformed line read");
+    }
+}
@@ -0,0 +1,122 @@
+//! Runs the bundled ROMs and the test programs in `tests/roms` headlessly
+//!
+//! The bundled ROMs are compared with the pictures in `tests/golden`, set
+//! `UPDATE_GOLDEN=1` to write them again after a deliberate change. The test
+//! programs write what they saw at their `results` label, which is checked
+//! against what every quirk profile should do.
+//!
+//! The test programs stand in for Timendus' chip8-test-suite, which is not
+//! vendored, and are built by our own assembler - see the README.
+
+use std::{env, fs, path::PathBuf};
+
+use chip8_rs::{assembler, screenshot, Machine};
+use chip8_rs::components::{platform::Platform, quirks::Quirks};
+
+/// Frames a program may take before it has to halt
+const FRAMES: u64 = 10_000;
+
+fn path(relative: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(relative)
+}
+
+/// Runs frames with the keys `keys` gives for each, until the program halts
+fn run_until_halted(machine: &mut Machine, name: &str, keys: impl Fn(u64) -> u16) {
+    while !machine.is_halted() {
+        assert!(machine.frames < FRAMES, "{} does not halt", name);
+        machine.run_frame(keys(machine.frames)).unwrap_or_else(|error| panic!("{} stopped: {}", name, error));
+    }
+}
+
+/// Assembles `tests/roms/<name>.8o`, runs it under `preset` and reads `count` bytes at its `results` label
+fn results(name: &str, preset: &str, count: usize, keys: impl Fn(u64) -> u16) -> Vec<u8> {
+    let source = fs::read_to_string(path(&format!("tests/roms/{}.8o", name))).unwrap();
+    let program = assembler::assemble(&source, Platform::Chip8).unwrap();
+    let at = program.symbols.address("results").expect("no results label") as usize;
+
+    let mut machine = Machine::with_quirks(Quirks::preset(preset).unwrap());
+    machine.load_rom(&program.rom).unwrap();
+    run_until_halted(&mut machine, &format!("{} under {}", name, preset), keys);
+    machine.cpu.memory[at..at + count].to_vec()
+}
+
+#[test]
+fn it_draws_the_bundled_roms() {
+    for name in ["ibm_logo", "test_opcode", "maze", "sierpinski"] {
+        let rom = fs::read(path(&format!("roms/{}.ch8", name))).unwrap();
+        let mut machine = Machine::new();
+        machine.load_rom(&rom).unwrap();
+        run_until_halted(&mut machine, name, |_| 0);
+
+        let picture = screenshot::ascii(machine.framebuffer());
+        let golden = path(&format!("tests/golden/{}.txt", name));
+        if env::var_os("UPDATE_GOLDEN").is_some() {
+            fs::write(&golden, &picture).unwrap();
+            continue;
+        }
+        assert_eq!(picture, fs::read_to_string(&golden).unwrap(), "{} draws another picture", name);
+    }
+}
+
+#[test]
+fn it_sets_flags() {
+    // result and VF of every check in flags.8o, the same on every platform
+    let expected = [
+        0x30, 0, 0x01, 1, // 8XY4
+        0x20, 1, 0xE0, 0, // 8XY5
+        0x01, 1, // 8XY6
+        0x20, 1, 0xE0, 0, // 8XY7
+        0x02, 1, 0x80, 0, // 8XYE
+        1, 0, 0, 0, 1, 0, 0, 0, 1, 0, // VF as the destination of 8XY4, 8XY5, 8XY6, 8XY7 and 8XYE
+    ];
+    for preset in Quirks::PRESETS {
+        assert_eq!(results("flags", preset, expected.len(), |_| 0), expected, "wrong flags under {}", preset);
+    }
+}
+
+#[test]
+fn it_runs_every_opcode() {
+    // what each check in opcodes.8o saw, 1 for a skip and 2 for no skip
+    let expected = [
+        0x2E, 1, // 2NNN and 00EE, 1NNN
+        1, 2, 1, 2, 1, 2, 1, 2, // 3XNN, 4XNN, 5XY0, 9XY0
+        0x42, 0x01, 7, // 6XNN, 7XNN and its VF
+        0x5A, 0x3F, 0x0C, 0x33, // 8XY0, 8XY1, 8XY2, 8XY3
+        0x30, 0x20, 0x20, 0x03, 0x0C, // 8XY4, 8XY5, 8XY7, 8XY6, 8XYE
+        0xA5, 0x5A, // ANNN, FX1E
+        1, 3, 7, 0x22, 0x33, // FX33, FX55 and FX65
+        0x20, 0, 0, // FX29, CXNN, FX15 and FX07
+        0, 1, 0, // DXYN and 00E0
+        1, 2, // EXA1, EX9E
+    ];
+    for preset in Quirks::PRESETS {
+        assert_eq!(results("opcodes", preset, expected.len(), |_| 0), expected, "wrong results under {}", preset);
+    }
+}
+
+#[test]
+fn it_follows_each_quirk_profile() {
+    // BNNN adds VX, VF after OR, AND and XOR, FX55 moves I, 8XY6 shifts VX, sprites wrap, sprites wait
+    let expected: [(&str, [u8; 8]); 4] = [
+        ("chip8", [0, 0, 0, 0, 0xCC, 0x08, 0, 1]),
+        ("chip48", [1, 5, 5, 5, 0x22, 0x02, 0, 0]),
+        ("schip", [1, 5, 5, 5, 0x11, 0x02, 0, 0]),
+        ("xochip", [0, 5, 5, 5, 0xCC, 0x08, 1, 0]),
+    ];
+    for (preset, expected) in expected {
+        assert_eq!(results("quirks", preset, expected.len(), |_| 0), expected, "wrong quirks under {}", preset);
+    }
+}
+
+#[test]
+fn it_reads_the_keypad() {
+    // 7 is pressed and let go, then 5 is held for a while
+    let keys = |frame| match frame {
+        3..=4 => 1 << 0x7,
+        10..=12 => 1 << 0x5,
+        _ => 0,
+    };
+    for preset in Quirks::PRESETS {
+        assert_eq!(results("keypad", preset, 3, keys), [7, 1, 2], "keypad read wrong under {}", preset);
+    }
+}